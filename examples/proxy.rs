@@ -19,17 +19,20 @@ pub fn main() {
     reactor.accept(srv)
         // Process client connections with at most 10 in-flight at any given
         // time.
-        .process(10, move |(src_tx, src_rx)| {
-            println!(" + Handling socket");
+        .process(10, move |conn| {
+            println!(" + Handling socket from {:?}", conn.peer_addr());
+            let (src_tx, src_rx) = conn.into_pair();
 
             // Hard coded to a google IP
-            let (client, _) = tcp::connect(&"216.58.216.164:80".parse().unwrap()).unwrap();
-            let (dst_tx, dst_rx) = r.stream(client);
+            let client = r.connect(&"216.58.216.164:80".parse().unwrap());
 
-            let a = dst_tx.send_all(src_rx).map_err(|_| ());
-            let b = src_tx.send_all(dst_rx).map_err(|_| ());
+            client.and_then(move |conn| {
+                let (dst_tx, dst_rx) = conn.into_pair();
+                let a = dst_tx.send_all(src_rx).map_err(|(e, _)| e);
+                let b = src_tx.send_all(dst_rx).map_err(|(e, _)| e);
 
-            eventual::join((a, b)).and_then(|v| {
+                eventual::join((a, b))
+            }).and_then(|v| {
                 println!(" + Socket done");
                 Ok(v)
             })
@@ -0,0 +1,59 @@
+use std::{error, fmt, io};
+
+/// Error returned by io ops
+#[derive(Debug)]
+pub enum Error {
+    /// An IO operation on the underlying socket failed
+    Io(io::Error),
+    /// The reactor is no longer running
+    Shutdown,
+    /// The connection timed out
+    Timeout,
+    /// The byte stream could not be split into frames
+    Frame(&'static str),
+}
+
+impl Error {
+    /// Returns the underlying `io::Error` if there is one
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(fmt, "io error: {}", e),
+            Error::Shutdown => write!(fmt, "reactor shutdown"),
+            Error::Timeout => write!(fmt, "connection timed out"),
+            Error::Frame(msg) => write!(fmt, "framing error: {}", msg),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(ref e) => e.description(),
+            Error::Shutdown => "reactor shutdown",
+            Error::Timeout => "connection timed out",
+            Error::Frame(msg) => msg,
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
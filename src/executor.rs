@@ -0,0 +1,129 @@
+use std::{cmp, fmt};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+/// Runs the tasks that complete a stream's consumers and producers, which in
+/// turn run the user's callbacks. By default, tasks run inline on the event
+/// loop thread, so a slow callback stalls all other sockets on that event
+/// loop.
+#[derive(Clone)]
+pub struct Executor {
+    inner: Option<Arc<Execute>>,
+}
+
+impl Executor {
+    /// Run tasks on the event loop thread
+    pub fn inline() -> Executor {
+        Executor { inner: None }
+    }
+
+    /// Run tasks on a dedicated pool of `n` threads
+    pub fn thread_pool(n: usize) -> Executor {
+        Executor::new(ThreadPool::new(n))
+    }
+
+    /// Run tasks using the given implementation
+    pub fn new<E: Execute>(execute: E) -> Executor {
+        Executor { inner: Some(Arc::new(execute)) }
+    }
+
+    pub fn execute<F: FnOnce() + Send + 'static>(&self, f: F) {
+        match self.inner {
+            Some(ref inner) => inner.execute(Task { f: Box::new(f) }),
+            None => f(),
+        }
+    }
+
+    pub fn is_inline(&self) -> bool {
+        self.inner.is_none()
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Executor {
+        Executor::inline()
+    }
+}
+
+impl fmt::Debug for Executor {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_inline() {
+            write!(fmt, "Executor::Inline")
+        } else {
+            write!(fmt, "Executor {{ ... }}")
+        }
+    }
+}
+
+/// Implemented by user supplied executors
+pub trait Execute: Send + Sync + 'static {
+    /// Run the task, usually on another thread
+    fn execute(&self, task: Task);
+}
+
+/// A unit of work submitted by the reactor
+pub struct Task {
+    f: Box<FnBox + Send>,
+}
+
+impl Task {
+    pub fn run(self) {
+        self.f.call_box()
+    }
+}
+
+impl fmt::Debug for Task {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Task {{ ... }}")
+    }
+}
+
+// Allows calling a boxed `FnOnce`
+trait FnBox {
+    fn call_box(self: Box<Self>);
+}
+
+impl<F: FnOnce()> FnBox for F {
+    fn call_box(self: Box<F>) {
+        (*self)()
+    }
+}
+
+// A fixed number of threads running tasks from a shared queue. The threads
+// exit once the pool has been dropped.
+struct ThreadPool {
+    tx: Mutex<mpsc::Sender<Task>>,
+}
+
+impl ThreadPool {
+    fn new(n: usize) -> ThreadPool {
+        let (tx, rx) = mpsc::channel::<Task>();
+        let rx = Arc::new(Mutex::new(rx));
+
+        for _ in 0..cmp::max(n, 1) {
+            let rx = rx.clone();
+
+            thread::spawn(move || {
+                loop {
+                    // Only hold the lock while waiting for the next task
+                    let task = rx.lock().unwrap().recv();
+
+                    match task {
+                        Ok(task) => task.run(),
+                        Err(_) => return,
+                    }
+                }
+            });
+        }
+
+        ThreadPool { tx: Mutex::new(tx) }
+    }
+}
+
+impl Execute for ThreadPool {
+    fn execute(&self, task: Task) {
+        if let Err(_) = self.tx.lock().unwrap().send(task) {
+            error!("ThreadPool::execute; all worker threads are gone");
+        }
+    }
+}
@@ -94,7 +94,14 @@ pub fn frame_one<F>(src: Stream<Bytes>,
             Ok(None) => {
                 match framer.next() {
                     Some(bytes) => dst.complete(Some((bytes, Stream::empty()))),
-                    None => dst.complete(None),
+                    None => {
+                        // The source is done, any data still buffered is an
+                        // incomplete frame
+                        match framer.flush() {
+                            Some(_) => dst.fail(Error::Frame("unexpected EOF in the middle of a frame")),
+                            None => dst.complete(None),
+                        }
+                    }
                 }
             }
             Err(AsyncError::Failed(e)) => {
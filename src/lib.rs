@@ -1,16 +1,28 @@
 extern crate bytes;
 extern crate eventual;
+extern crate libc;
 extern crate mio;
+extern crate nix;
 
 #[macro_use]
 extern crate log;
 
 pub mod frame;
 
+mod error;
+mod executor;
 mod net;
+mod process;
 mod reactor;
+mod signal;
+mod sys;
 
-pub use reactor::Reactor;
+pub use error::Error;
+pub use executor::{Execute, Executor, Task};
+pub use net::{BufferPool, BufferStrategy, Connection, Credentials, Io, StreamOptions};
+pub use process::Child;
+pub use reactor::{Reactor, ReactorBuilder, ShutdownPolicy};
+pub use signal::Signal;
 
 /*
  *
@@ -38,9 +50,6 @@ mod core {
 
 use std::result;
 
-/// Error returned by io ops
-pub type Error = ();
-
 pub type Result<T> = result::Result<T, eventual::AsyncError<Error>>;
 pub type Future<T> = eventual::Future<T, Error>;
 pub type Stream<T> = eventual::Stream<T, Error>;
@@ -0,0 +1,167 @@
+use bytes::{ByteBuf, Bytes, MutByteBuf};
+use std::{cmp, fmt};
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_BUF_SIZE: usize = 4096;
+
+// Buffer capacities are rounded up to a power of two that has to fit the 32
+// bit capacity of `bytes` buffers
+const MAX_BUF_SIZE: usize = 1 << 31;
+
+/// Determines how a stream allocates the buffers that data is read into
+#[derive(Clone, Debug)]
+pub enum BufferStrategy {
+    /// Read into a newly allocated buffer of the given size. The buffer is
+    /// handed to the consumer as is.
+    Fixed(usize),
+    /// Read into a newly allocated buffer, starting at `min` bytes. The size
+    /// doubles, up to `max`, whenever a read fills the buffer and halves,
+    /// down to `min`, whenever a read uses less than a quarter of it.
+    Adaptive { min: usize, max: usize },
+    /// Read into a buffer taken from the pool. The data is copied into an
+    /// exactly sized chunk for the consumer and the buffer is returned to the
+    /// pool, so idle connections do not hold on to large buffers.
+    Pooled(BufferPool),
+}
+
+// Bring the sizes within range up front, allocating the buffers on the event
+// loop would panic there instead
+pub fn clamp(strategy: BufferStrategy) -> BufferStrategy {
+    match strategy {
+        BufferStrategy::Fixed(size) => BufferStrategy::Fixed(clamp_size(size)),
+        BufferStrategy::Adaptive { min, max } => {
+            let max = clamp_size(max);
+            BufferStrategy::Adaptive { min: cmp::min(clamp_size(min), max), max: max }
+        }
+        // Clamped when the pool is created
+        strategy => strategy,
+    }
+}
+
+fn clamp_size(size: usize) -> usize {
+    cmp::min(cmp::max(size, 1), MAX_BUF_SIZE)
+}
+
+impl Default for BufferStrategy {
+    fn default() -> BufferStrategy {
+        BufferStrategy::Fixed(DEFAULT_BUF_SIZE)
+    }
+}
+
+/// A pool of read buffers that can be shared between streams
+#[derive(Clone)]
+pub struct BufferPool {
+    inner: Arc<PoolInner>,
+}
+
+struct PoolInner {
+    buf_size: usize,
+    // Maximum number of idle buffers kept around
+    capacity: usize,
+    bufs: Mutex<Vec<MutByteBuf>>,
+}
+
+impl BufferPool {
+    /// Create a pool of `buf_size` byte buffers, keeping at most `capacity`
+    /// unused buffers around for reuse. The buffer size is at least 1 byte
+    /// and at most 2GB.
+    pub fn new(buf_size: usize, capacity: usize) -> BufferPool {
+        BufferPool {
+            inner: Arc::new(PoolInner {
+                buf_size: clamp_size(buf_size),
+                capacity: capacity,
+                bufs: Mutex::new(Vec::with_capacity(capacity)),
+            }),
+        }
+    }
+
+    /// Number of unused buffers currently kept for reuse
+    pub fn idle(&self) -> usize {
+        self.inner.bufs.lock().unwrap().len()
+    }
+
+    fn get(&self) -> MutByteBuf {
+        match self.inner.bufs.lock().unwrap().pop() {
+            Some(buf) => buf,
+            None => ByteBuf::mut_with_capacity(self.inner.buf_size),
+        }
+    }
+
+    fn put(&self, mut buf: MutByteBuf) {
+        let mut bufs = self.inner.bufs.lock().unwrap();
+
+        if bufs.len() < self.inner.capacity {
+            buf.clear();
+            bufs.push(buf);
+        }
+    }
+}
+
+impl fmt::Debug for BufferPool {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "BufferPool {{ buf_size: {}, capacity: {} }}",
+               self.inner.buf_size, self.inner.capacity)
+    }
+}
+
+// Allocates the read buffers of a single stream
+pub struct ReadBuffer {
+    strategy: BufferStrategy,
+    // Size of the next buffer, only used by the adaptive strategy
+    next: usize,
+}
+
+impl ReadBuffer {
+    pub fn new(strategy: BufferStrategy) -> ReadBuffer {
+        let next = match strategy {
+            BufferStrategy::Adaptive { min, .. } => min,
+            _ => 0,
+        };
+
+        ReadBuffer {
+            strategy: strategy,
+            next: next,
+        }
+    }
+
+    // Returns a buffer to read into
+    pub fn get(&mut self) -> MutByteBuf {
+        match self.strategy {
+            BufferStrategy::Fixed(size) => ByteBuf::mut_with_capacity(size),
+            BufferStrategy::Adaptive { .. } => ByteBuf::mut_with_capacity(self.next),
+            BufferStrategy::Pooled(ref pool) => pool.get(),
+        }
+    }
+
+    // Convert a buffer that data has been read into to the bytes handed to
+    // the consumer
+    pub fn take(&mut self, buf: MutByteBuf) -> Bytes {
+        match self.strategy {
+            BufferStrategy::Fixed(..) => buf.flip().to_bytes(),
+            BufferStrategy::Adaptive { min, max } => {
+                let len = buf.bytes().len();
+                let cap = buf.capacity();
+
+                if len == cap {
+                    self.next = cmp::min(cap * 2, max);
+                } else if len < cap / 4 {
+                    self.next = cmp::max(cap / 2, min);
+                }
+
+                buf.flip().to_bytes()
+            }
+            BufferStrategy::Pooled(ref pool) => {
+                let bytes = Bytes::from_slice(buf.bytes());
+                pool.put(buf);
+                bytes
+            }
+        }
+    }
+
+    // Nothing was read into the buffer
+    pub fn release(&mut self, buf: MutByteBuf) {
+        if let BufferStrategy::Pooled(ref pool) = self.strategy {
+            pool.put(buf);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use core::{self, Complete, Error};
+use mio::NonBlock;
+use mio::tcp::TcpStream;
+use net::{self, Connection, Stream, StreamOptions};
+use std::fmt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use sys;
+
+// A socket in the process of connecting to a remote address. Once the socket
+// becomes writable, the result of the connect is checked and the consumer is
+// handed the connection.
+pub struct Connect {
+    io: NonBlock<TcpStream>,
+    tx: Complete<Connection>,
+    options: StreamOptions,
+    // Set once the socket is known to the event loop
+    registered: bool,
+}
+
+impl Connect {
+    pub fn of(io: NonBlock<TcpStream>, options: StreamOptions) -> (Connect, core::Future<Connection>) {
+        let (tx, rx) = core::Future::pair();
+
+        let connect = Connect {
+            io: io,
+            tx: tx,
+            options: options,
+            registered: false,
+        };
+
+        (connect, rx)
+    }
+
+    // The socket's file descriptor, along with whether it has been registered
+    // with the event loop yet
+    pub fn registration(&mut self) -> (RawFd, &mut bool) {
+        (self.io.as_raw_fd(), &mut self.registered)
+    }
+
+    // The socket is ready, check whether or not the connect succeeded. On
+    // success, returns the stream that the reactor should start managing.
+    pub fn connected(self) -> Option<Stream> {
+        match sys::take_socket_error(&self.io) {
+            Ok(None) => {
+                debug!("Connect::connected; socket connected");
+
+                let (mut stream, pair) = Stream::of(self.io, &self.options);
+                let conn = net::connection(stream.io(), pair);
+
+                // The socket stays registered with the event loop, the stream
+                // only has to re-arm it
+                if self.registered {
+                    stream.set_registered();
+                }
+
+                let tx = self.tx;
+
+                self.options.executor.execute(move || tx.complete(conn));
+
+                Some(stream)
+            }
+            Ok(Some(err)) | Err(err) => {
+                self.fail(Error::Io(err));
+                None
+            }
+        }
+    }
+
+    pub fn fail(self, err: Error) {
+        debug!("Connect::fail; err={:?}", err);
+        let tx = self.tx;
+
+        self.options.executor.execute(move || tx.fail(err));
+    }
+}
+
+impl fmt::Debug for Connect {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "eventual_io::Connect {{ ... }}")
+    }
+}
@@ -1,9 +1,14 @@
-use core::{self, async, Async, Bytes, Pair, Sender};
+use core::{self, async, Async, Error, Sender};
+use libc;
 use mio::{NonBlock, Token};
-use mio::tcp::TcpListener;
-use net::{Action, Stream};
+use mio::tcp::{TcpListener, TcpStream};
+use mio::unix::{UnixListener, UnixStream};
+use net::{self, Action, Connection, Stream, StreamOptions};
 use reactor::Notify;
-use std::{fmt, mem};
+use std::{fmt, fs, io, mem};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::PathBuf;
+use sys;
 
 // ## Implementation notes
 //
@@ -12,26 +17,54 @@ use std::{fmt, mem};
 // long as this is possible, the listener must stay in the slab
 
 pub struct Listener {
-    io: NonBlock<TcpListener>,
+    io: Socket,
     state: State,
+    // Options applied to accepted streams
+    options: StreamOptions,
+    // Set once the socket is known to the event loop
+    registered: bool,
 }
 
 impl Listener {
-    pub fn of(io: NonBlock<TcpListener>) -> (Listener, core::Stream<Pair<Bytes>>) {
+    pub fn of(io: NonBlock<TcpListener>, options: StreamOptions) -> (Listener, core::Stream<Connection>) {
         // Core Stream
         let (tx, rx) = async::Pair::pair();
-        (Listener::new(io, tx), rx)
+        (Listener::new(Socket::Tcp(io), tx, options), rx)
     }
 
-    fn new(io: NonBlock<TcpListener>, tx: Sender<Pair<Bytes>>) -> Listener {
+    // Listen on a Unix socket bound to the given path. The socket file is
+    // removed once the listener is closed.
+    pub fn unix(io: NonBlock<UnixListener>, path: PathBuf, options: StreamOptions) -> (Listener, core::Stream<Connection>) {
+        let (tx, rx) = async::Pair::pair();
+        let id = net::unix::file_id(&path).ok();
+        (Listener::new(Socket::Unix(io, path, id), tx, options), rx)
+    }
+
+    fn new(io: Socket, tx: Sender<Connection>, options: StreamOptions) -> Listener {
         Listener {
             io: io,
             state: State::New { tx: tx },
+            options: options,
+            registered: false,
         }
     }
 
-    pub fn io(&self) -> &NonBlock<TcpListener> {
-        &self.io
+    // The socket's file descriptor, along with whether it has been registered
+    // with the event loop yet
+    pub fn registration(&mut self) -> (RawFd, &mut bool) {
+        (self.io().as_raw_fd(), &mut self.registered)
+    }
+
+    pub fn io(&self) -> &AsRawFd {
+        match self.io {
+            Socket::Tcp(ref io) => io,
+            Socket::Unix(ref io, _, _) => io,
+        }
+    }
+
+    // Maximum number of sockets to accept per readiness event
+    pub fn budget(&self) -> usize {
+        self.options.event_budget
     }
 
     pub fn listen(&mut self, notify: &Notify, token: Token) -> Action {
@@ -45,50 +78,135 @@ impl Listener {
             }
         }
 
-        self.wait(tx, notify, token);
+        Listener::wait(tx, notify, token);
         Action::wait()
     }
 
-    pub fn ready(&mut self, tx: Sender<Pair<Bytes>>) -> Action {
+    pub fn ready(&mut self, tx: Sender<Connection>) -> Action {
         self.state.waiting_to_listening(tx);
         Action::read()
     }
 
-    pub fn accept(&mut self, notify: &Notify, token: Token) -> Option<(Stream, Action)> {
+    pub fn accept(&mut self, notify: &Notify, token: Token) -> (Option<Stream>, Action) {
         // Get the sender
         let tx = self.state.listening_to_waiting();
 
-        // Accept a socket
-        let sock = match self.io.accept() {
-            Ok(Some(sock)) => sock,
-            Ok(None) => unimplemented!(),
-            Err(_) => panic!("[unimplemented] failed to accept socket"),
+        // Accept a socket and build the stream wrapper for it
+        let res = match self.io {
+            Socket::Tcp(ref io) => {
+                io.accept().map(|sock| sock.map(|sock| tcp_stream(sock, &self.options)))
+            }
+            Socket::Unix(ref io, _, _) => {
+                io.accept().map(|sock| sock.map(|sock| unix_stream(sock, &self.options)))
+            }
         };
 
-        // Build the stream wrapper for the socket
-        let (stream, pair) = Stream::of(sock);
+        let (stream, conn) = match res {
+            Ok(Some(Ok(accepted))) => accepted,
+            Ok(Some(Err(e))) => {
+                // Drop the socket, closing it, and move on to the next one
+                debug!("Listener::accept; failed to set up socket; err={:?}", e);
+                return (None, self.ready(tx));
+            }
+            Ok(None) => {
+                // Spurious wakeup, wait for the next readiness event
+                debug!("Listener::accept; no pending socket");
+                return (None, self.ready(tx));
+            }
+            Err(e) => {
+                return (None, self.accept_failed(tx, e));
+            }
+        };
 
         debug!("Listener::accept; ~ Sending socket to consumer");
 
-        // Send the sender / stream pair to the user
-        let busy = tx.send(pair);
+        if !self.options.executor.is_inline() {
+            // Send the connection from the executor, the reactor is notified once
+            // the consumer is ready for the next socket
+            let notify = notify.clone();
+
+            self.options.executor.execute(move || {
+                Listener::wait(tx.send(conn), &notify, token);
+            });
+
+            return (Some(stream), Action::wait());
+        }
+
+        // Send the connection to the user
+        let busy = tx.send(conn);
 
         match busy.poll() {
             Ok(Ok(tx)) => {
-                Some((stream, self.ready(tx)))
+                (Some(stream), self.ready(tx))
             }
             Ok(Err(_)) => {
-                Some((stream, Action::remove()))
+                (Some(stream), Action::remove())
             }
             Err(busy) => {
-                self.wait(busy, notify, token);
-                Some((stream, Action::wait()))
+                Listener::wait(busy, notify, token);
+                (Some(stream), Action::wait())
+            }
+        }
+    }
+
+    // Registering the listener with the event loop failed, there is no way to
+    // recover, so pass the error on to the consumer.
+    pub fn fail(&mut self, err: io::Error) -> Action {
+        let tx = self.state.listening_to_waiting();
+        self.options.executor.execute(move || tx.fail(Error::Io(err)));
+        Action::remove()
+    }
+
+    // The reactor is shutting down, fail the consumer. If the consumer is
+    // currently busy, it is failed once it is ready for the next socket.
+    pub fn shutdown(&mut self) -> Action {
+        self.abort(Error::Shutdown)
+    }
+
+    // Close the listener, failing the consumer with the given error
+    pub fn abort(&mut self, err: Error) -> Action {
+        match mem::replace(&mut self.state, State::Waiting) {
+            State::New { tx } | State::Listening { tx } => {
+                self.options.executor.execute(move || tx.fail(err));
             }
+            State::Waiting => {}
         }
+
+        Action::remove()
     }
 
-    fn wait<A>(&mut self, tx: A, notify: &Notify, token: Token)
-            where A: Async<Value=Sender<Pair<Bytes>>> {
+    // Determine how to proceed after a failed accept. Errors caused by the
+    // pending connection itself are skipped, resource exhaustion backs off
+    // for a bit, and anything else is fatal to the listener.
+    fn accept_failed(&mut self, tx: Sender<Connection>, err: io::Error) -> Action {
+        match err.raw_os_error() {
+            Some(libc::ECONNABORTED) |
+            Some(libc::EINTR) |
+            Some(libc::EPERM) |
+            Some(libc::ENETDOWN) |
+            Some(libc::ENETUNREACH) |
+            Some(libc::EHOSTUNREACH) => {
+                debug!("Listener::accept; transient error, retrying; err={:?}", err);
+                self.ready(tx)
+            }
+            Some(libc::EMFILE) |
+            Some(libc::ENFILE) |
+            Some(libc::ENOBUFS) |
+            Some(libc::ENOMEM) => {
+                warn!("Listener::accept; out of resources, backing off; err={:?}", err);
+                self.state.waiting_to_listening(tx);
+                Action::backoff()
+            }
+            _ => {
+                debug!("Listener::accept; failed to accept socket; err={:?}", err);
+                self.options.executor.execute(move || tx.fail(Error::Io(err)));
+                Action::remove()
+            }
+        }
+    }
+
+    fn wait<A>(tx: A, notify: &Notify, token: Token)
+            where A: Async<Value=Sender<Connection>> {
 
         // Wait for interest to be registered before attempting to accept
         // from the socket
@@ -96,47 +214,83 @@ impl Listener {
         tx.receive(move |res| {
             debug!("Listener::wait; socket ready");
             match res {
-                Ok(tx) => {
-                    if notify.accept_interest(Some(tx), token) {
-                        return;
-                    }
-                }
-                Err(_) => {
-                    // The consumer has dropped the stream and does not want
-                    // any new sockets. The listener can be closed.
-                    if notify.accept_interest(None, token) {
-                        return;
-                    }
-                }
-            }
-
-            panic!("[unimplemented] failed to notify reactor of ready listener");
+                Ok(tx) => notify.accept_interest(Some(tx), token),
+                // The consumer has dropped the stream and does not want any
+                // new sockets. The listener can be closed.
+                Err(_) => notify.accept_interest(None, token),
+            }
         });
     }
 }
 
+// Configure an accepted TCP socket and build the stream managing it. Fails if
+// the peer is already gone.
+fn tcp_stream(sock: NonBlock<TcpStream>, options: &StreamOptions) -> io::Result<(Stream, Connection)> {
+    try!(net::configure(&sock, options));
+
+    // Fails with `ENOTCONN` if the peer has already closed the connection
+    try!(sys::peer_addr(&sock));
+
+    let (stream, pair) = Stream::of(sock, options);
+    let conn = net::connection(stream.io(), pair);
+
+    Ok((stream, conn))
+}
+
+// Build the stream managing an accepted Unix socket. The socket options only
+// apply to TCP sockets.
+fn unix_stream(sock: NonBlock<UnixStream>, options: &StreamOptions) -> io::Result<(Stream, Connection)> {
+    let (stream, pair) = Stream::of(sock, options);
+    let conn = net::unix::connection(stream.io(), pair);
+
+    Ok((stream, conn))
+}
+
+enum Socket {
+    Tcp(NonBlock<TcpListener>),
+    // The path of the socket file, removed once the listener is dropped,
+    // along with the file's device and inode as they were when it was bound
+    Unix(NonBlock<UnixListener>, PathBuf, Option<(u64, u64)>),
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        if let Socket::Unix(_, ref path, id) = *self {
+            // Another socket may have been bound to the path since
+            if id.is_none() || net::unix::file_id(path).ok() != id {
+                debug!("Listener::drop; socket file replaced, leaving it; path={:?}", path);
+                return;
+            }
+
+            if let Err(e) = fs::remove_file(path) {
+                debug!("Listener::drop; failed to remove socket file; err={:?}", e);
+            }
+        }
+    }
+}
+
 enum State {
-    New { tx: Sender<Pair<Bytes>> },
+    New { tx: Sender<Connection> },
     Waiting,
-    Listening { tx: Sender<Pair<Bytes>> },
+    Listening { tx: Sender<Connection> },
 }
 
 impl State {
-    fn new_to_waiting(&mut self) -> Sender<Pair<Bytes>> {
+    fn new_to_waiting(&mut self) -> Sender<Connection> {
         match mem::replace(self, State::Waiting) {
             State::New { tx } => tx,
             _ => panic!("unexpected state"),
         }
     }
 
-    fn waiting_to_listening(&mut self, tx: Sender<Pair<Bytes>>) {
+    fn waiting_to_listening(&mut self, tx: Sender<Connection>) {
         match mem::replace(self, State::Listening { tx: tx }) {
             State::Waiting => {},
             _ => panic!("unexpected state"),
         }
     }
 
-    fn listening_to_waiting(&mut self) -> Sender<Pair<Bytes>> {
+    fn listening_to_waiting(&mut self) -> Sender<Connection> {
         match mem::replace(self, State::Waiting) {
             State::Listening { tx} => tx,
             _ => panic!("unexpected state"),
@@ -1,13 +1,270 @@
+mod buffer;
+mod connect;
 mod listener;
 mod stream;
+mod udp;
 
+pub mod unix;
+
+pub use self::buffer::{BufferPool, BufferStrategy};
+pub use self::connect::Connect;
 pub use self::listener::Listener;
-pub use self::stream::Stream;
+pub use self::stream::{Io, Stream};
+pub use self::udp::Udp;
+pub use self::unix::Credentials;
+
+use core::{AsyncError, Bytes, Error, Pair};
+use executor::Executor;
+use signal;
+use std::{cmp, fmt, io};
+use std::net::SocketAddr;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::Duration;
+use sys;
+
+// Number of reads, writes or accepts performed per readiness event
+const DEFAULT_EVENT_BUDGET: usize = 16;
+
+/// Options applied to the streams managed by the reactor
+#[derive(Clone, Debug)]
+pub struct StreamOptions {
+    idle_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    linger_timeout: Option<Duration>,
+    buffer_strategy: BufferStrategy,
+    event_budget: usize,
+    executor: Executor,
+    nodelay: Option<bool>,
+    keepalive: Option<Duration>,
+    send_buffer_size: Option<usize>,
+    recv_buffer_size: Option<usize>,
+    socket_linger: Option<Duration>,
+}
+
+impl StreamOptions {
+    pub fn new() -> StreamOptions {
+        StreamOptions::default()
+    }
+
+    /// Close the stream if no data has been read or written for the given
+    /// duration.
+    pub fn idle_timeout(mut self, dur: Duration) -> StreamOptions {
+        self.idle_timeout = Some(dur);
+        self
+    }
+
+    /// Close the stream if the consumer is waiting for data and none has
+    /// been received for the given duration.
+    pub fn read_timeout(mut self, dur: Duration) -> StreamOptions {
+        self.read_timeout = Some(dur);
+        self
+    }
+
+    /// Close the stream if pending data could not be written to the socket
+    /// within the given duration.
+    pub fn write_timeout(mut self, dur: Duration) -> StreamOptions {
+        self.write_timeout = Some(dur);
+        self
+    }
+
+    /// Limit how long data that has already been received from the producer
+    /// keeps being written once the stream is closing. Past the deadline, the
+    /// remaining data is discarded and the socket closed. Without a deadline,
+    /// closing waits for the data to be flushed.
+    pub fn linger_timeout(mut self, dur: Duration) -> StreamOptions {
+        self.linger_timeout = Some(dur);
+        self
+    }
+
+    /// How buffers for reading from the socket are allocated. Defaults to
+    /// `BufferStrategy::Fixed(4096)`. Buffer sizes are at least 1 byte and at
+    /// most 2GB, and the adaptive minimum is at most the maximum.
+    pub fn buffer_strategy(mut self, strategy: BufferStrategy) -> StreamOptions {
+        self.buffer_strategy = buffer::clamp(strategy);
+        self
+    }
+
+    /// Maximum number of reads, and of writes, performed for a single
+    /// readiness event, so that one busy stream cannot starve the others.
+    /// Once the budget is used up, the stream waits for the next event. For
+    /// listeners, this bounds the number of sockets accepted per event.
+    /// Defaults to 16, the minimum is 1.
+    pub fn event_budget(mut self, budget: usize) -> StreamOptions {
+        self.event_budget = cmp::max(budget, 1);
+        self
+    }
+
+    /// Where data and errors are handed to the stream's consumer and
+    /// producer, and where the user's callbacks run as a result. For
+    /// listeners, this also applies to handing out accepted streams.
+    /// Defaults to `Executor::inline()`.
+    pub fn executor(mut self, executor: Executor) -> StreamOptions {
+        self.executor = executor;
+        self
+    }
+
+    /// Set `TCP_NODELAY` on the socket. When true, small writes are sent
+    /// right away instead of being coalesced.
+    pub fn nodelay(mut self, val: bool) -> StreamOptions {
+        self.nodelay = Some(val);
+        self
+    }
+
+    /// Enable TCP keepalive, probing the peer once the connection has been
+    /// idle for the given duration.
+    pub fn keepalive(mut self, idle: Duration) -> StreamOptions {
+        self.keepalive = Some(idle);
+        self
+    }
+
+    /// Set the size of the socket's send buffer (`SO_SNDBUF`)
+    pub fn send_buffer_size(mut self, size: usize) -> StreamOptions {
+        self.send_buffer_size = Some(size);
+        self
+    }
+
+    /// Set the size of the socket's receive buffer (`SO_RCVBUF`)
+    pub fn recv_buffer_size(mut self, size: usize) -> StreamOptions {
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// Set `SO_LINGER` on the socket, bounding how long the kernel keeps
+    /// sending unsent data after the socket is closed. The duration is
+    /// rounded up to whole seconds. With a zero duration, the connection is
+    /// reset on close. This is unrelated to `linger_timeout`, which applies
+    /// to data the reactor has not yet written to the socket.
+    pub fn socket_linger(mut self, dur: Duration) -> StreamOptions {
+        self.socket_linger = Some(dur);
+        self
+    }
+}
+
+impl Default for StreamOptions {
+    fn default() -> StreamOptions {
+        StreamOptions {
+            idle_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
+            linger_timeout: None,
+            buffer_strategy: BufferStrategy::default(),
+            event_budget: DEFAULT_EVENT_BUDGET,
+            executor: Executor::inline(),
+            nodelay: None,
+            keepalive: None,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+            socket_linger: None,
+        }
+    }
+}
+
+// Apply the socket options to a new or accepted socket. Options that have
+// not been set are left at the system default.
+pub fn configure<S: AsRawFd + ?Sized>(sock: &S, options: &StreamOptions) -> io::Result<()> {
+    if let Some(val) = options.nodelay {
+        try!(sys::set_nodelay(sock, val));
+    }
+
+    if let Some(idle) = options.keepalive {
+        try!(sys::set_keepalive(sock, idle));
+    }
+
+    if let Some(size) = options.send_buffer_size {
+        try!(sys::set_send_buffer_size(sock, size));
+    }
+
+    if let Some(size) = options.recv_buffer_size {
+        try!(sys::set_recv_buffer_size(sock, size));
+    }
+
+    if let Some(dur) = options.socket_linger {
+        try!(sys::set_linger(sock, dur));
+    }
+
+    Ok(())
+}
+
+/// A stream managed by the reactor, along with the addresses of its socket
+pub struct Connection {
+    pair: Pair<Bytes>,
+    peer_addr: Option<SocketAddr>,
+    local_addr: Option<SocketAddr>,
+    peer_cred: Option<Credentials>,
+}
+
+impl Connection {
+    fn new(pair: Pair<Bytes>,
+           peer_addr: Option<SocketAddr>,
+           local_addr: Option<SocketAddr>,
+           peer_cred: Option<Credentials>) -> Connection {
+
+        Connection {
+            pair: pair,
+            peer_addr: peer_addr,
+            local_addr: local_addr,
+            peer_cred: peer_cred,
+        }
+    }
+
+    /// Address of the remote end of the connection. Always known for accepted
+    /// TCP connections. For a socket handed to `Reactor::stream` while it was
+    /// still connecting, the address is not known yet and `None` is returned.
+    /// Unix sockets have no such address.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// Address of the local end of the connection, `None` for Unix sockets
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// Credentials of the peer process of a Unix socket (`SO_PEERCRED`).
+    /// `None` for TCP connections and on platforms without `SO_PEERCRED`.
+    pub fn peer_cred(&self) -> Option<Credentials> {
+        self.peer_cred
+    }
+
+    /// Returns the sender used to write to the connection and the stream of
+    /// data read from it
+    pub fn into_pair(self) -> Pair<Bytes> {
+        self.pair
+    }
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Connection")
+            .field("peer_addr", &self.peer_addr)
+            .field("local_addr", &self.local_addr)
+            .field("peer_cred", &self.peer_cred)
+            .finish()
+    }
+}
+
+// Wrap the pair of a stream along with its socket's addresses
+pub fn connection<S: AsRawFd + ?Sized>(sock: &S, pair: Pair<Bytes>) -> Connection {
+    Connection::new(pair, sys::peer_addr(sock).ok(), sys::local_addr(sock).ok(), None)
+}
+
+// The error that the consumer is failed with when the producer fails
+pub fn producer_error(err: AsyncError<Error>) -> Error {
+    match err {
+        AsyncError::Failed(err) => err,
+        AsyncError::Aborted => Error::Io(io::Error::new(io::ErrorKind::Other, "producer aborted")),
+    }
+}
 
 #[derive(Debug)]
 pub enum Evented {
     Stream(Stream),
     Listener(Listener),
+    Connect(Connect),
+    Udp(Udp),
+    // Receives the signals delivered to the reactor
+    Signals(signal::Receiver),
 }
 
 impl Evented {
@@ -24,11 +281,46 @@ impl Evented {
             _ => panic!("expected Evented to be net::Stream"),
         }
     }
+
+    pub fn udp(&mut self) -> &mut Udp {
+        match *self {
+            Evented::Udp(ref mut v) => v,
+            _ => panic!("expected Evented to be net::Udp"),
+        }
+    }
+
+    pub fn signals(&mut self) -> &mut signal::Receiver {
+        match *self {
+            Evented::Signals(ref mut v) => v,
+            _ => panic!("expected Evented to be signal::Receiver"),
+        }
+    }
+
+    // The socket's file descriptor, along with whether it has been registered
+    // with the event loop yet
+    pub fn registration(&mut self) -> (RawFd, &mut bool) {
+        match *self {
+            Evented::Stream(ref mut v) => v.registration(),
+            Evented::Listener(ref mut v) => v.registration(),
+            Evented::Connect(ref mut v) => v.registration(),
+            Evented::Udp(ref mut v) => v.registration(),
+            Evented::Signals(ref mut v) => v.registration(),
+        }
+    }
+
+    pub fn into_connect(self) -> Connect {
+        match self {
+            Evented::Connect(v) => v,
+            _ => panic!("expected Evented to be net::Connect"),
+        }
+    }
 }
 
 pub enum Action {
     Wait,
     Register(Interest),
+    // Stop listening for events for a short period of time
+    Backoff,
     Remove,
 }
 
@@ -37,13 +329,6 @@ impl Action {
         Action::Wait
     }
 
-    pub fn is_register(&self) -> bool {
-        match *self {
-            Action::Register(..) => true,
-            _ => false,
-        }
-    }
-
     pub fn read() -> Action {
         Action::Register(Interest::Read)
     }
@@ -56,6 +341,10 @@ impl Action {
         Action::Register(Interest::ReadWrite)
     }
 
+    pub fn backoff() -> Action {
+        Action::Backoff
+    }
+
     pub fn remove() -> Action {
         Action::Remove
     }
@@ -66,3 +355,12 @@ pub enum Interest {
     Write,
     ReadWrite,
 }
+
+#[derive(Copy, Clone, Debug)]
+pub enum Timeout {
+    Idle,
+    Read,
+    Write,
+    // Flushing pending data while closing took too long
+    Linger,
+}
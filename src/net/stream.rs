@@ -1,33 +1,123 @@
-use bytes::{ByteStr, Buf, ByteBuf};
-use core::{self, Async, Bytes, Pair, Sender};
-use mio::{NonBlock, TryRead, TryWrite, Token};
+use bytes::{ByteStr, Buf};
+use core::{self, Async, Bytes, Error, Pair, Sender};
+use core::async::{Cancel, Receipt};
+use executor::Executor;
+use mio::{self, EventLoop, NonBlock, Token};
 use mio::tcp::TcpStream;
-use net::Action;
-use reactor::Notify;
-use std::{fmt, mem};
+use mio::unix::UnixStream;
+use net::{self, Action, StreamOptions, Timeout};
+use net::buffer::ReadBuffer;
+use reactor::{self, IoHandler, Notify, ShutdownPolicy};
+use std::{cmp, fmt, io, mem};
+use std::collections::VecDeque;
+use std::net::Shutdown;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use sys;
+
+// Maximum number of chunks received from the producer that are queued for
+// writing. Queued chunks are written with a single `writev` call.
+const MAX_QUEUED_CHUNKS: usize = 64;
+
+// Interest registered in the producer's next chunk. Cancelling it hands back
+// the producer's stream, unless the chunk is already on its way.
+type Pending = Arc<Mutex<Option<Receipt<core::Stream<Bytes>>>>>;
+
+/// A non-blocking file descriptor that the reactor can manage as a stream,
+/// such as a socket, a pipe, an eventfd or a tun device. The stream reads
+/// from and writes to the file descriptor directly with `read(2)` and
+/// `writev(2)`, and the file descriptor is closed once the stream is done.
+///
+/// For a file descriptor that only supports one direction, drop the unused
+/// half of the pair returned by `Reactor::register`.
+pub trait Io: mio::Evented + Send {
+    /// Called once the producer is done (`Shutdown::Write`), when reading
+    /// stops while pending data is still being flushed (`Shutdown::Read`) and
+    /// when the stream fails (`Shutdown::Both`). Does nothing by default,
+    /// which suits file descriptors other than sockets.
+    fn shutdown(&self, _how: Shutdown) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Io for NonBlock<TcpStream> {
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        TcpStream::shutdown(self, how)
+    }
+}
+
+impl Io for NonBlock<UnixStream> {
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        sys::shutdown(self, how)
+    }
+}
+
+impl Io for mio::Io {
+}
+
+impl Io for mio::PipeReader {
+}
+
+impl Io for mio::PipeWriter {
+}
 
 pub struct Stream {
-    io: NonBlock<TcpStream>,
+    io: Box<Io>,
     reading: Reading,
     writing: Writing,
+    buffer: ReadBuffer,
+    // Maximum number of reads or writes per readiness event
+    budget: usize,
+    // Runs the tasks that complete the consumer
+    executor: Executor,
+    timeouts: Timeouts,
+    // Set when data is read, used to reset the idle and read timeouts
+    received: bool,
+    // Set when data is written, used to reset the idle and write timeouts
+    sent: bool,
+    // Set once the socket is known to the event loop
+    registered: bool,
+    // The last interest registered in the producer's next chunk
+    pending: Option<Pending>,
 }
 
 impl Stream {
-    pub fn of(io: NonBlock<TcpStream>) -> (Stream, Pair<Bytes>) {
+    pub fn of<T: Io + 'static>(io: T, options: &StreamOptions) -> (Stream, Pair<Bytes>) {
         let (read_tx, read_rx) = core::Stream::pair();
         let (write_tx, write_rx) = core::Stream::pair();
 
         let stream = Stream {
-            io: io,
+            io: Box::new(io),
             reading: Reading::New { tx: read_tx },
             writing: Writing::New { rx: write_rx },
+            buffer: ReadBuffer::new(options.buffer_strategy.clone()),
+            budget: options.event_budget,
+            executor: options.executor.clone(),
+            timeouts: Timeouts::new(options),
+            received: false,
+            sent: false,
+            registered: false,
+            pending: None,
         };
 
         (stream, (write_tx, read_rx))
     }
 
-    pub fn io(&self) -> &NonBlock<TcpStream> {
-        &self.io
+    // The socket's file descriptor, along with whether it has been registered
+    // with the event loop yet
+    pub fn registration(&mut self) -> (RawFd, &mut bool) {
+        (self.io.as_raw_fd(), &mut self.registered)
+    }
+
+    // The socket was registered with the event loop before being handed to
+    // the stream, i.e. while connecting
+    pub fn set_registered(&mut self) {
+        self.registered = true;
+    }
+
+    pub fn io(&self) -> &Io {
+        &*self.io
     }
 
     // Initialize the stream state, returning the action that the reactor
@@ -43,7 +133,7 @@ impl Stream {
                 self.reading.close()
             }
             Err(tx) => {
-                self.read_wait(tx, notify, token)
+                Stream::read_wait(tx, notify, token)
             }
         }
 
@@ -51,13 +141,13 @@ impl Stream {
 
         match rx.poll() {
             Ok(Ok(Some((bytes, rx)))) => {
-                self.write_interest(bytes, rx);
+                self.write_interest(bytes, rx, notify, token);
             }
             Ok(Ok(None)) => {
-                self.writing.close()
+                self.close_write()
             }
-            Ok(Err(_)) => {
-                panic!("[unimplemented] gotta handle errors");
+            Ok(Err(err)) => {
+                self.write_failed(net::producer_error(err));
             }
             Err(rx) => {
                 self.write_wait(rx, notify, token);
@@ -74,7 +164,13 @@ impl Stream {
      */
 
     pub fn read_interest(&mut self, tx: Sender<Bytes>) -> Action {
-        self.reading.waiting_to_reading(tx);
+        match self.reading.take_err() {
+            // The socket failed while the consumer was busy, deliver the
+            // error now that it is ready for more data.
+            Some(err) => self.executor.execute(move || tx.fail(err)),
+            None => self.reading.waiting_to_reading(tx),
+        }
+
         self.action()
     }
 
@@ -85,35 +181,85 @@ impl Stream {
     }
 
     pub fn read(&mut self, notify: &Notify, token: Token) -> Action {
-        let tx = self.reading.reading_to_waiting();
+        let mut tx = self.reading.reading_to_waiting();
+        let mut budget = self.budget;
+
+        // Keep reading as long as there is data and the consumer is ready for
+        // it, up to the budget.
+        loop {
+            let mut buf = self.buffer.get();
+
+            match sys::read(&*self.io, &mut buf) {
+                Ok(Some(0)) => {
+                    // The read end of the socket has been closed
+                    self.buffer.release(buf);
+                    self.reading.close();
+                }
+                Ok(Some(_)) => {
+                    self.received = true;
+                    budget -= 1;
 
-        // TODO: Use a customizable buffer allocation strategy
-        let mut buf = ByteBuf::mut_with_capacity(4096);
+                    let bytes = self.buffer.take(buf);
 
-        match self.io.read(&mut buf) {
-            Ok(Some(0)) => {
-                // The read end of the socket has been closed
-                self.reading.close();
-            }
-            Ok(Some(_)) => {
-                // Send bytes to teh consumer
-                let busy = tx.send(buf.flip().to_bytes());
-                self.read_wait(busy, notify, token);
-            }
-            Ok(None) => {
-                // Nothing to do, re-register, put back in the reading state.
-                // The socket will be re-registered with the event loop
-                self.reading.waiting_to_reading(tx);
-            }
-            Err(_) => {
-                panic!("[unimplemented] read failed");
+                    if !self.executor.is_inline() {
+                        // Send bytes to the consumer from the executor, the
+                        // reactor is notified once the consumer is ready for
+                        // more.
+                        let notify = notify.clone();
+
+                        self.executor.execute(move || {
+                            Stream::read_wait(tx.send(bytes), &notify, token);
+                        });
+
+                        return self.action();
+                    }
+
+                    // Send bytes to teh consumer
+                    let busy = tx.send(bytes);
+
+                    match busy.poll() {
+                        Ok(Ok(next)) => {
+                            if budget > 0 {
+                                tx = next;
+                                continue;
+                            }
+
+                            // Out of budget, the socket is re-registered and
+                            // reading resumes on the next event
+                            self.reading.waiting_to_reading(next);
+                        }
+                        Ok(Err(_)) => {
+                            // Consumer is no longer interested in reading
+                            self.reading.close();
+                        }
+                        Err(busy) => {
+                            Stream::read_wait(busy, notify, token);
+                        }
+                    }
+                }
+                Ok(None) => {
+                    // Nothing to do, re-register, put back in the reading state.
+                    // The socket will be re-registered with the event loop
+                    self.buffer.release(buf);
+                    self.reading.waiting_to_reading(tx);
+                }
+                Err(e) => {
+                    debug!("Stream::read; socket failed; err={:?}", e);
+                    self.buffer.release(buf);
+
+                    // Stop writing and notify the consumer of the failure
+                    self.abort_write();
+                    self.executor.execute(move || tx.fail(Error::Io(e)));
+                    self.reading.close();
+                    self.shutdown_io();
+                }
             }
-        }
 
-        self.action()
+            return self.action();
+        }
     }
 
-    fn read_wait<A>(&mut self, tx: A, notify: &Notify, token: Token)
+    fn read_wait<A>(tx: A, notify: &Notify, token: Token)
             where A: Async<Value=Sender<Bytes>> {
 
         debug!("Stream::read_wait");
@@ -122,83 +268,312 @@ impl Stream {
         tx.receive(move |res| {
             debug!("Stream::read_wait; the consumer has registered interest");
             match res {
-                Ok(tx) => {
-                    if notify.stream_read_ready(Some(tx), token) {
-                        return;
-                    }
-                }
-                Err(_) => {
-                    // Consumer is no longer interested in reading
-                    if notify.stream_read_ready(None, token) {
-                        return;
-                    }
-                }
+                Ok(tx) => notify.stream_read_ready(Some(tx), token),
+                // Consumer is no longer interested in reading
+                Err(_) => notify.stream_read_ready(None, token),
             }
-
-            panic!("[unimplemented] failed to notify reactor of ready listener");
         });
     }
 
     /*
      *
-     * ===== Read =====
+     * ===== Write =====
      *
      */
 
-    pub fn write_interest(&mut self, bytes: Bytes, rx: core::Stream<Bytes>) -> Action {
+    pub fn write_interest(&mut self,
+                          bytes: Bytes,
+                          rx: core::Stream<Bytes>,
+                          notify: &Notify,
+                          token: Token) -> Action {
+
         debug!("Stream::write_interest; received data, waiting for writability");
-        self.writing.waiting_to_writing(bytes.buf(), rx);
+
+        // If the data is discarded, dropping `rx` lets the producer know that
+        // the write half is closed.
+        self.writing.push(bytes.buf(), rx);
+        self.write_more(notify, token);
         self.action()
     }
 
     pub fn write_close(&mut self) -> Action {
         debug!("Stream::write_close");
-        self.writing.close();
+        self.close_write();
         self.action()
     }
 
     pub fn write(&mut self, notify: &Notify, token: Token) -> Action {
-        let (mut buf, rx) = self.writing.writing_to_waiting();
-
         debug!("Stream::write; token={:?}", token);
 
-        // TODO: There should probably be an iteration cap
-        while buf.has_remaining() {
-            match self.io.write(&mut buf) {
-                Ok(Some(_)) => {}
+        let mut budget = self.budget;
+
+        while !self.writing.queue().is_empty() {
+            if budget == 0 {
+                // Yield to other sockets, the stream stays registered for
+                // writability and continues on the next event.
+                debug!("Stream::write; out of budget");
+                self.write_more(notify, token);
+                return self.action();
+            }
+
+            budget -= 1;
+
+            let res = sys::writev(&*self.io, &slices(self.writing.queue()));
+
+            match res {
+                Ok(Some(n)) => {
+                    self.sent = true;
+                    advance(self.writing.queue(), n);
+                }
                 Ok(None) => {
-                    self.writing.waiting_to_writing(buf, rx);
+                    // Room was made in the queue, ask the producer for more
+                    // data while waiting for writability
+                    self.write_more(notify, token);
                     return self.action();
                 }
-                Err(_) => {
-                    panic!("[unimplemented] write failed");
+                Err(e) => {
+                    debug!("Stream::write; socket failed; err={:?}", e);
+
+                    // Drop the pending data & the producer's stream, then
+                    // notify the consumer of the failure
+                    self.abort_write();
+                    self.reading.fail(Error::Io(e), &self.executor);
+                    self.shutdown_io();
+
+                    return self.action();
                 }
             }
         }
 
-        // Wait for more bytes
-        self.write_wait(rx, notify, token);
+        // All queued data has been written
+        match self.writing.writing_to_waiting() {
+            // Already waiting for more bytes
+            Producer::Waiting => {}
+            // Wait for more bytes
+            Producer::Ready(rx) => self.write_wait(rx, notify, token),
+            // The stream is closing, but the producer is still busy
+            Producer::Aborted => self.abort_write(),
+            // The producer is done or the stream is closing
+            Producer::Done => self.close_write(),
+        }
+
         self.action()
     }
 
+    // Ask the producer for the next chunk if there is room in the queue
+    fn write_more(&mut self, notify: &Notify, token: Token) {
+        if let Some(rx) = self.writing.take_ready() {
+            self.write_wait(rx, notify, token);
+        }
+    }
+
+    // The producer is done and all of its data has been written. Shutdown the
+    // write half of the socket so the peer sees EOF, reads keep flowing until
+    // the peer closes its end.
+    fn close_write(&mut self) {
+        self.writing.end();
+
+        if !self.writing.is_closed() {
+            // The write half is shutdown once the queued data has been
+            // flushed.
+            return;
+        }
+
+        if let Err(e) = self.io.shutdown(Shutdown::Write) {
+            debug!("Stream::close_write; failed to shutdown socket; err={:?}", e);
+        }
+    }
+
     fn write_wait(&mut self, rx: core::Stream<Bytes>, notify: &Notify, token: Token) {
         debug!("Stream::write_wait; token={:?}", token);
 
         let notify = notify.clone();
-        rx.receive(move |res| {
-            match res {
-                Ok(head) => {
-                    if notify.stream_write_ready(head, token) {
-                        return;
+        let pending = Arc::new(Mutex::new(None));
+
+        self.pending = Some(pending.clone());
+
+        // Registering interest may run the producer's callbacks
+        self.executor.execute(move || {
+            let receipt = rx.ready(move |rx| {
+                rx.receive(move |res| {
+                    match res {
+                        Ok(head) => notify.stream_write_ready(head, token),
+                        Err(err) => notify.stream_write_failed(net::producer_error(err), token),
                     }
-                }
-                Err(_) => panic!("[unimplemented] consumer failed"),
-            }
+                });
+            });
 
-            panic!("[unimplemented] failed to notify reactor of ready listener");
+            *pending.lock().unwrap() = Some(receipt);
         });
     }
 
+    // The producer's stream failed. Pending data is dropped and the consumer
+    // is failed with the producer's error.
+    pub fn write_failed(&mut self, err: Error) -> Action {
+        debug!("Stream::write_failed; err={:?}", err);
+        self.pending = None;
+        self.writing.close();
+        self.reading.fail(err, &self.executor);
+        self.shutdown_io();
+        self.action()
+    }
+
+    // Cancel the interest in the producer's next chunk, dropping the
+    // producer's stream so that the producer learns right away that the
+    // write half is closed. Returns false if the chunk is already on its way
+    // to the reactor, or interest has not been registered yet.
+    fn cancel_write_wait(&mut self) -> bool {
+        let receipt = self.pending.take()
+            .and_then(|pending| pending.lock().unwrap().take());
+
+        match receipt.and_then(|receipt| receipt.cancel()) {
+            Some(rx) => {
+                drop(rx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Drop any pending data and stop accepting data from the producer
+    fn abort_write(&mut self) {
+        let cancelled = self.writing.is_waiting() && self.cancel_write_wait();
+        self.writing.abort(cancelled);
+    }
+
+    // Stop accepting data from the producer, but keep writing the data that
+    // is already queued
+    fn flush_write(&mut self) {
+        let cancelled = self.writing.is_waiting() && self.cancel_write_wait();
+        self.writing.flush(cancelled);
+    }
+
+    /*
+     *
+     * ===== Timeouts =====
+     *
+     */
+
+    // Arm or clear the stream's timeouts to match its current state. Called
+    // by the reactor after processing an event for the stream. Fails if a
+    // timeout could not be scheduled.
+    pub fn update_timeouts(&mut self,
+                           event_loop: &mut EventLoop<IoHandler>,
+                           token: Token) -> Result<(), Error> {
+
+        // Transferring data resets the idle timeout, and the timeout of the
+        // direction that made progress
+        let received = mem::replace(&mut self.received, false);
+        let sent = mem::replace(&mut self.sent, false);
+
+        if received || sent {
+            self.timeouts.idle.clear(event_loop);
+        }
+
+        if received {
+            self.timeouts.read.clear(event_loop);
+        }
+
+        if sent {
+            self.timeouts.write.clear(event_loop);
+        }
+
+        let open = !self.reading.is_closed() || !self.writing.is_closed();
+        let reading = self.reading.is_reading();
+        let writing = self.writing.is_writing();
+        let lingering = self.writing.is_lingering();
+
+        try!(self.timeouts.idle.update(open, event_loop, token, Timeout::Idle));
+        try!(self.timeouts.read.update(reading, event_loop, token, Timeout::Read));
+        try!(self.timeouts.write.update(writing, event_loop, token, Timeout::Write));
+        try!(self.timeouts.linger.update(lingering, event_loop, token, Timeout::Linger));
+
+        Ok(())
+    }
+
+    // Clear all pending timeouts, called before the stream is removed from
+    // the reactor.
+    pub fn clear_timeouts(&mut self, event_loop: &mut EventLoop<IoHandler>) {
+        self.timeouts.idle.clear(event_loop);
+        self.timeouts.read.clear(event_loop);
+        self.timeouts.write.clear(event_loop);
+        self.timeouts.linger.clear(event_loop);
+    }
+
+    pub fn timed_out(&mut self, timeout: Timeout) -> Action {
+        debug!("Stream::timed_out; timeout={:?}", timeout);
+
+        // The timer has fired, so there is nothing to clear anymore
+        self.timeouts.get(timeout).pending = None;
+
+        match timeout {
+            // Only the read side timed out, data that is pending can still be
+            // written.
+            Timeout::Read => self.linger(Error::Timeout),
+            _ => self.fail(Error::Timeout),
+        }
+    }
+
+    // Fail the consumer and close the socket once data that has already been
+    // received from the producer has been written. The flush is bounded by
+    // the linger timeout.
+    fn linger(&mut self, err: Error) -> Action {
+        debug!("Stream::linger; err={:?}", err);
+        self.reading.fail(err, &self.executor);
+        self.flush_write();
+
+        if self.writing.is_lingering() {
+            if let Err(e) = self.io.shutdown(Shutdown::Read) {
+                debug!("Stream::linger; failed to shutdown socket; err={:?}", e);
+            }
+        } else {
+            self.shutdown_io();
+        }
+
+        self.action()
+    }
+
+    // Fail the consumer and the producer and close the socket, dropping any
+    // pending data
+    pub fn fail(&mut self, err: Error) -> Action {
+        debug!("Stream::fail; err={:?}", err);
+        self.abort_write();
+        self.reading.fail(err, &self.executor);
+        self.shutdown_io();
+        self.action()
+    }
+
+    // The reactor is shutting down. The consumer is failed and, depending on
+    // the policy, the data already received from the producer is either
+    // flushed, bounded by the linger timeout, or dropped. Returns
+    // `Action::write()` while there is data left to flush, and
+    // `Action::remove()` once the stream can be removed.
+    pub fn shutdown(&mut self, policy: ShutdownPolicy) -> Action {
+        debug!("Stream::shutdown; policy={:?}", policy);
+
+        self.reading.fail(Error::Shutdown, &self.executor);
+
+        match policy {
+            ShutdownPolicy::Flush => self.flush_write(),
+            ShutdownPolicy::Discard => self.abort_write(),
+        }
+
+        if self.writing.is_writing() {
+            Action::write()
+        } else {
+            Action::remove()
+        }
+    }
+
+    // Shutdown both halves of the socket. The socket is only closed once it is
+    // removed from the reactor, which has to wait until all outstanding
+    // consumer / producer callbacks have been received.
+    fn shutdown_io(&self) {
+        if let Err(e) = self.io.shutdown(Shutdown::Both) {
+            debug!("Stream::shutdown_io; failed to shutdown socket; err={:?}", e);
+        }
+    }
+
     fn action(&self) -> Action {
         // Convert state to action
         match (&self.reading, &self.writing) {
@@ -217,6 +592,8 @@ enum Reading {
     New { tx: Sender<Bytes> },
     Waiting,
     Reading { tx: Sender<Bytes> },
+    // The socket failed while waiting for the consumer
+    Failed { err: Error },
     Closed,
 }
 
@@ -242,6 +619,42 @@ impl Reading {
         }
     }
 
+    // Fail the consumer with the given error. If the consumer is currently
+    // busy, the error is stored until it is ready for more data.
+    fn fail(&mut self, err: Error, executor: &Executor) {
+        match mem::replace(self, Reading::Closed) {
+            Reading::New { tx } | Reading::Reading { tx } => {
+                executor.execute(move || tx.fail(err));
+            }
+            Reading::Waiting => *self = Reading::Failed { err: err },
+            _ => {}
+        }
+    }
+
+    fn take_err(&mut self) -> Option<Error> {
+        match mem::replace(self, Reading::Closed) {
+            Reading::Failed { err } => Some(err),
+            state => {
+                *self = state;
+                None
+            }
+        }
+    }
+
+    fn is_reading(&self) -> bool {
+        match *self {
+            Reading::Reading { .. } => true,
+            _ => false,
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        match *self {
+            Reading::Failed { .. } | Reading::Closed => true,
+            _ => false,
+        }
+    }
+
     fn close(&mut self) {
         mem::replace(self, Reading::Closed);
     }
@@ -249,11 +662,29 @@ impl Reading {
 
 enum Writing {
     New { rx: core::Stream<Bytes> },
+    // Waiting on the producer, nothing is queued
     Waiting,
-    Writing { buf: Box<Buf+'static>, rx: core::Stream<Bytes> },
+    // Writing queued data to the socket
+    Writing { queue: VecDeque<Box<Buf+'static>>, producer: Producer },
+    // The socket failed while waiting for the producer
+    Aborted,
     Closed,
 }
 
+// The state of the producer while data is queued for writing
+enum Producer {
+    // Interest in the next chunk has been registered
+    Waiting,
+    // The queue is full, more data is requested once there is room
+    Ready(core::Stream<Bytes>),
+    // The stream is closing while interest in the next chunk is registered,
+    // the chunk will be discarded
+    Aborted,
+    // No more data is accepted, the write half is closed once the queue has
+    // been flushed
+    Done,
+}
+
 impl Writing {
     fn new_to_waiting(&mut self) -> core::Stream<Bytes> {
         match mem::replace(self, Writing::Waiting) {
@@ -262,24 +693,245 @@ impl Writing {
         }
     }
 
-    fn waiting_to_writing(&mut self, buf: Box<Buf+'static>, rx: core::Stream<Bytes>) {
-        match mem::replace(self, Writing::Writing { buf: buf, rx: rx }) {
-            Writing::Waiting => {},
+    // Queue data received from the producer. `rx` is held on to until there
+    // is room for more data in the queue.
+    fn push(&mut self, buf: Box<Buf+'static>, rx: core::Stream<Bytes>) {
+        match mem::replace(self, Writing::Closed) {
+            Writing::Waiting => {
+                let mut queue = VecDeque::new();
+                queue.push_back(buf);
+
+                *self = Writing::Writing { queue: queue, producer: Producer::Ready(rx) };
+            }
+            Writing::Writing { mut queue, producer: Producer::Waiting } => {
+                queue.push_back(buf);
+
+                *self = Writing::Writing { queue: queue, producer: Producer::Ready(rx) };
+            }
+            Writing::Writing { queue, producer: Producer::Aborted } => {
+                debug!("Writing::push; stream closing, discarding data");
+                *self = Writing::Writing { queue: queue, producer: Producer::Done };
+            }
+            Writing::Aborted => {
+                debug!("Writing::push; socket failed, discarding data");
+            }
+            _ => panic!("unexpected state"),
+        }
+    }
+
+    // Returns the producer's stream if there is room for more data
+    fn take_ready(&mut self) -> Option<core::Stream<Bytes>> {
+        if let Writing::Writing { ref queue, ref mut producer } = *self {
+            if queue.len() < MAX_QUEUED_CHUNKS {
+                // Any other state of the producer is left as it is
+                match mem::replace(producer, Producer::Waiting) {
+                    Producer::Ready(rx) => return Some(rx),
+                    state => *producer = state,
+                }
+            }
+        }
+
+        None
+    }
+
+    fn queue(&mut self) -> &mut VecDeque<Box<Buf+'static>> {
+        match *self {
+            Writing::Writing { ref mut queue, .. } => queue,
             _ => panic!("unexpected state"),
         }
     }
 
-    fn writing_to_waiting(&mut self) -> (Box<Buf+'static>, core::Stream<Bytes>) {
+    // The queue has been flushed
+    fn writing_to_waiting(&mut self) -> Producer {
         match mem::replace(self, Writing::Waiting) {
-            Writing::Writing { buf, rx } => (buf, rx),
+            Writing::Writing { producer, .. } => producer,
             _ => panic!("unexpected state"),
         }
     }
 
+    // The producer is done, queued data is still written
+    fn end(&mut self) {
+        match mem::replace(self, Writing::Closed) {
+            Writing::Writing { queue, .. } => {
+                *self = Writing::Writing { queue: queue, producer: Producer::Done };
+            }
+            _ => {}
+        }
+    }
+
+    // The socket failed, any pending data is dropped. Unless the interest in
+    // the producer's next chunk was cancelled, the data it sends next will be
+    // discarded.
+    fn abort(&mut self, cancelled: bool) {
+        match mem::replace(self, Writing::Closed) {
+            Writing::Waiting |
+            Writing::Writing { producer: Producer::Waiting, .. } |
+            Writing::Writing { producer: Producer::Aborted, .. } if !cancelled => {
+                *self = Writing::Aborted;
+            }
+            _ => {}
+        }
+    }
+
+    // Finish writing the queued data without accepting more from the
+    // producer. Dropping `rx` lets the producer know that the write half is
+    // closed.
+    fn flush(&mut self, cancelled: bool) {
+        match mem::replace(self, Writing::Closed) {
+            Writing::Waiting if !cancelled => *self = Writing::Aborted,
+            Writing::Waiting => {}
+            Writing::Writing { queue, producer } => {
+                let producer = match producer {
+                    Producer::Waiting | Producer::Aborted if !cancelled => Producer::Aborted,
+                    _ => Producer::Done,
+                };
+
+                *self = Writing::Writing { queue: queue, producer: producer };
+            }
+            state => *self = state,
+        }
+    }
+
+    // Whether the queued data is being flushed before the stream closes
+    fn is_lingering(&self) -> bool {
+        match *self {
+            Writing::Writing { producer: Producer::Aborted, .. } |
+            Writing::Writing { producer: Producer::Done, .. } => true,
+            _ => false,
+        }
+    }
+
+    fn is_writing(&self) -> bool {
+        match *self {
+            Writing::Writing { .. } => true,
+            _ => false,
+        }
+    }
+
+    // Whether interest in the producer's next chunk is registered
+    fn is_waiting(&self) -> bool {
+        match *self {
+            Writing::Waiting |
+            Writing::Writing { producer: Producer::Waiting, .. } => true,
+            _ => false,
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        match *self {
+            Writing::Aborted | Writing::Closed => true,
+            _ => false,
+        }
+    }
+
     fn close(&mut self) {
-        if let Writing::Writing { .. } = mem::replace(self, Writing::Closed) {
-            panic!("[unimplemented] the current buf needs to be flushd (unless
-                    the sock is being closed due to an IO error");
+        mem::replace(self, Writing::Closed);
+    }
+}
+
+// The slices handed to `writev`. A chunk that is not contiguous ends the list,
+// as the rest of its data has to be written before the next chunk.
+fn slices<'a>(queue: &'a VecDeque<Box<Buf+'static>>) -> Vec<&'a [u8]> {
+    let mut ret = Vec::with_capacity(queue.len());
+
+    for buf in queue {
+        let bytes = buf.bytes();
+        ret.push(bytes);
+
+        if bytes.len() < buf.remaining() {
+            break;
+        }
+    }
+
+    ret
+}
+
+// Consume `n` written bytes from the front of the queue
+fn advance(queue: &mut VecDeque<Box<Buf+'static>>, mut n: usize) {
+    loop {
+        match queue.front_mut() {
+            Some(buf) => {
+                let cnt = cmp::min(n, buf.remaining());
+                buf.advance(cnt);
+                n -= cnt;
+
+                if buf.has_remaining() {
+                    return;
+                }
+            }
+            None => return,
+        }
+
+        queue.pop_front();
+    }
+}
+
+struct Timeouts {
+    idle: Timer,
+    read: Timer,
+    write: Timer,
+    linger: Timer,
+}
+
+impl Timeouts {
+    fn new(options: &StreamOptions) -> Timeouts {
+        Timeouts {
+            idle: Timer::new(options.idle_timeout),
+            read: Timer::new(options.read_timeout),
+            write: Timer::new(options.write_timeout),
+            linger: Timer::new(options.linger_timeout),
+        }
+    }
+
+    fn get(&mut self, timeout: Timeout) -> &mut Timer {
+        match timeout {
+            Timeout::Idle => &mut self.idle,
+            Timeout::Read => &mut self.read,
+            Timeout::Write => &mut self.write,
+            Timeout::Linger => &mut self.linger,
+        }
+    }
+}
+
+// A configured timeout and its pending timer, if any
+struct Timer {
+    ms: Option<u64>,
+    pending: Option<mio::Timeout>,
+}
+
+impl Timer {
+    fn new(dur: Option<Duration>) -> Timer {
+        Timer {
+            ms: dur.map(reactor::millis),
+            pending: None,
+        }
+    }
+
+    // Ensure the timer is pending if `armed` is set, cleared otherwise
+    fn update(&mut self,
+              armed: bool,
+              event_loop: &mut EventLoop<IoHandler>,
+              token: Token,
+              timeout: Timeout) -> Result<(), Error> {
+
+        if !armed {
+            self.clear(event_loop);
+            return Ok(());
+        }
+
+        if let (Some(ms), None) = (self.ms, self.pending) {
+            match event_loop.timeout_ms(reactor::Timeout::Stream(token, timeout), ms) {
+                Ok(pending) => self.pending = Some(pending),
+                Err(_) => return Err(reactor::too_many_timers()),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn clear(&mut self, event_loop: &mut EventLoop<IoHandler>) {
+        if let Some(pending) = self.pending.take() {
+            event_loop.clear_timeout(pending);
         }
     }
 }
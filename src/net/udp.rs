@@ -0,0 +1,460 @@
+use bytes::{Buf, ByteStr};
+use core::{self, Async, Bytes, Error, Pair, Sender};
+use executor::Executor;
+use mio::{NonBlock, Token};
+use mio::udp::UdpSocket;
+use net::{self, Action, StreamOptions};
+use net::buffer::ReadBuffer;
+use reactor::{Notify, ShutdownPolicy};
+use std::{fmt, io, mem};
+use std::net::SocketAddr;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+// ## Implementation notes
+//
+// A UDP socket follows the same consumer driven model as `Stream`: the socket
+// is only read from while the consumer is ready for the next datagram, and
+// the next datagram is only requested from the producer once the previous
+// one has been sent. As with streams, the socket must stay in the slab while
+// a consumer or producer callback is outstanding.
+
+pub struct Udp {
+    io: NonBlock<UdpSocket>,
+    reading: Reading,
+    writing: Writing,
+    // Datagrams that do not fit in the buffer are truncated
+    buffer: ReadBuffer,
+    // Maximum number of datagrams received per readiness event
+    budget: usize,
+    // Runs the tasks that complete the consumer
+    executor: Executor,
+    // Set once the socket is known to the event loop
+    registered: bool,
+}
+
+impl Udp {
+    pub fn of(io: NonBlock<UdpSocket>, options: &StreamOptions) -> (Udp, Pair<(Bytes, SocketAddr)>) {
+        let (read_tx, read_rx) = core::Stream::pair();
+        let (write_tx, write_rx) = core::Stream::pair();
+
+        let udp = Udp {
+            io: io,
+            reading: Reading::New { tx: read_tx },
+            writing: Writing::New { rx: write_rx },
+            buffer: ReadBuffer::new(options.buffer_strategy.clone()),
+            budget: options.event_budget,
+            executor: options.executor.clone(),
+            registered: false,
+        };
+
+        (udp, (write_tx, read_rx))
+    }
+
+    // The socket's file descriptor, along with whether it has been registered
+    // with the event loop yet
+    pub fn registration(&mut self) -> (RawFd, &mut bool) {
+        (self.io.as_raw_fd(), &mut self.registered)
+    }
+
+    // Initialize the socket state, returning the action that the reactor
+    // should perform on the socket
+    pub fn init(&mut self, notify: &Notify, token: Token) -> Action {
+        let tx = self.reading.new_to_waiting();
+
+        match tx.poll() {
+            Ok(Ok(tx)) => self.reading.waiting_to_reading(tx),
+            Ok(Err(_)) => self.reading.close(),
+            Err(tx) => Udp::read_wait(tx, notify, token),
+        }
+
+        let rx = self.writing.new_to_waiting();
+
+        match rx.poll() {
+            Ok(Ok(Some((datagram, rx)))) => self.writing.push(datagram, rx),
+            Ok(Ok(None)) => self.writing.close(),
+            Ok(Err(err)) => { self.write_failed(net::producer_error(err)); }
+            Err(rx) => self.write_wait(rx, notify, token),
+        }
+
+        self.action()
+    }
+
+    /*
+     *
+     * ===== Read =====
+     *
+     */
+
+    pub fn read_interest(&mut self, tx: Sender<(Bytes, SocketAddr)>) -> Action {
+        match self.reading.take_err() {
+            // The socket failed while the consumer was busy, deliver the
+            // error now that it is ready for more data.
+            Some(err) => self.executor.execute(move || tx.fail(err)),
+            None => self.reading.waiting_to_reading(tx),
+        }
+
+        self.action()
+    }
+
+    pub fn read_close(&mut self) -> Action {
+        debug!("Udp::read_close");
+        self.reading.close();
+        self.action()
+    }
+
+    pub fn read(&mut self, notify: &Notify, token: Token) -> Action {
+        // The socket may have failed since the event was delivered
+        if !self.reading.is_reading() {
+            return self.action();
+        }
+
+        let mut tx = self.reading.reading_to_waiting();
+        let mut budget = self.budget;
+
+        // Keep receiving as long as datagrams are pending and the consumer is
+        // ready for them, up to the budget.
+        loop {
+            let mut buf = self.buffer.get();
+
+            match self.io.recv_from(&mut buf) {
+                Ok(Some(addr)) => {
+                    budget -= 1;
+
+                    let datagram = (self.buffer.take(buf), addr);
+
+                    if !self.executor.is_inline() {
+                        let notify = notify.clone();
+
+                        self.executor.execute(move || {
+                            Udp::read_wait(tx.send(datagram), &notify, token);
+                        });
+
+                        return self.action();
+                    }
+
+                    let busy = tx.send(datagram);
+
+                    match busy.poll() {
+                        Ok(Ok(next)) => {
+                            if budget > 0 {
+                                tx = next;
+                                continue;
+                            }
+
+                            self.reading.waiting_to_reading(next);
+                        }
+                        Ok(Err(_)) => {
+                            // Consumer is no longer interested in datagrams
+                            self.reading.close();
+                        }
+                        Err(busy) => {
+                            Udp::read_wait(busy, notify, token);
+                        }
+                    }
+                }
+                Ok(None) => {
+                    self.buffer.release(buf);
+                    self.reading.waiting_to_reading(tx);
+                }
+                Err(e) => {
+                    debug!("Udp::read; socket failed; err={:?}", e);
+                    self.buffer.release(buf);
+
+                    self.executor.execute(move || tx.fail(Error::Io(e)));
+                    self.reading.close();
+                }
+            }
+
+            return self.action();
+        }
+    }
+
+    fn read_wait<A>(tx: A, notify: &Notify, token: Token)
+            where A: Async<Value=Sender<(Bytes, SocketAddr)>> {
+
+        let notify = notify.clone();
+        tx.receive(move |res| {
+            match res {
+                Ok(tx) => notify.udp_read_ready(Some(tx), token),
+                // Consumer is no longer interested in datagrams
+                Err(_) => notify.udp_read_ready(None, token),
+            }
+        });
+    }
+
+    /*
+     *
+     * ===== Write =====
+     *
+     */
+
+    pub fn write_interest(&mut self,
+                          datagram: (Bytes, SocketAddr),
+                          rx: core::Stream<(Bytes, SocketAddr)>) -> Action {
+
+        self.writing.push(datagram, rx);
+        self.action()
+    }
+
+    pub fn write_close(&mut self) -> Action {
+        debug!("Udp::write_close");
+        self.writing.close();
+        self.action()
+    }
+
+    pub fn write(&mut self, notify: &Notify, token: Token) -> Action {
+        if !self.writing.is_writing() {
+            return self.action();
+        }
+
+        let (mut buf, addr, rx) = self.writing.writing_to_waiting();
+
+        match self.io.send_to(&mut buf, &addr) {
+            Ok(Some(())) => {}
+            Ok(None) => {
+                // Wait for the socket to become writable again
+                self.writing.waiting_to_writing(buf, addr, rx);
+                return self.action();
+            }
+            Err(e) => {
+                // Only this datagram is lost, so carry on with the next one.
+                // The error is reported to the consumer, which ends the
+                // receive side.
+                debug!("Udp::write; failed to send datagram; err={:?}", e);
+                self.reading.fail(Error::Io(e), &self.executor);
+            }
+        }
+
+        match rx {
+            Some(rx) => self.write_wait(rx, notify, token),
+            // The last datagram has been flushed
+            None => self.writing.close(),
+        }
+
+        self.action()
+    }
+
+    fn write_wait(&mut self, rx: core::Stream<(Bytes, SocketAddr)>, notify: &Notify, token: Token) {
+        let notify = notify.clone();
+
+        // Registering interest may run the producer's callbacks
+        self.executor.execute(move || {
+            rx.receive(move |res| {
+                match res {
+                    Ok(head) => notify.udp_write_ready(head, token),
+                    Err(err) => notify.udp_write_failed(net::producer_error(err), token),
+                }
+            });
+        });
+    }
+
+    // The producer's stream failed. The consumer is failed with the
+    // producer's error.
+    pub fn write_failed(&mut self, err: Error) -> Action {
+        debug!("Udp::write_failed; err={:?}", err);
+        self.writing.close();
+        self.reading.fail(err, &self.executor);
+        self.action()
+    }
+
+    /*
+     *
+     * ===== Closing =====
+     *
+     */
+
+    // Fail the consumer and stop sending
+    pub fn fail(&mut self, err: Error) -> Action {
+        debug!("Udp::fail; err={:?}", err);
+        self.reading.fail(err, &self.executor);
+        self.writing.abort();
+        self.action()
+    }
+
+    // The reactor is shutting down. The consumer is failed and no more
+    // datagrams are accepted from the producer. Depending on the policy, a
+    // datagram that is waiting for the socket to become writable is either
+    // still sent or dropped.
+    pub fn shutdown(&mut self, policy: ShutdownPolicy) -> Action {
+        debug!("Udp::shutdown; policy={:?}", policy);
+        self.reading.fail(Error::Shutdown, &self.executor);
+
+        match policy {
+            ShutdownPolicy::Flush => self.writing.flush(),
+            ShutdownPolicy::Discard => self.writing.abort(),
+        }
+
+        self.action()
+    }
+
+    fn action(&self) -> Action {
+        match (&self.reading, &self.writing) {
+            (&Reading::Reading { .. }, &Writing::Writing { .. }) => Action::read_write(),
+            (&Reading::Reading { .. }, _                       ) => Action::read(),
+            (_,                        &Writing::Writing { .. }) => Action::write(),
+            (&Reading::Closed,         &Writing::Closed)         => Action::remove(),
+            _                                                    => Action::wait(),
+        }
+    }
+}
+
+unsafe impl Send for Udp { }
+
+enum Reading {
+    New { tx: Sender<(Bytes, SocketAddr)> },
+    Waiting,
+    Reading { tx: Sender<(Bytes, SocketAddr)> },
+    // The socket failed while waiting for the consumer
+    Failed { err: Error },
+    Closed,
+}
+
+impl Reading {
+    fn new_to_waiting(&mut self) -> Sender<(Bytes, SocketAddr)> {
+        match mem::replace(self, Reading::Waiting) {
+            Reading::New { tx } => tx,
+            _ => panic!("unexpected state"),
+        }
+    }
+
+    fn waiting_to_reading(&mut self, tx: Sender<(Bytes, SocketAddr)>) {
+        match mem::replace(self, Reading::Reading { tx: tx }) {
+            Reading::Waiting => {},
+            _ => panic!("unexpected state"),
+        }
+    }
+
+    fn reading_to_waiting(&mut self) -> Sender<(Bytes, SocketAddr)> {
+        match mem::replace(self, Reading::Waiting) {
+            Reading::Reading { tx } => tx,
+            _ => panic!("unexpected state"),
+        }
+    }
+
+    fn is_reading(&self) -> bool {
+        match *self {
+            Reading::Reading { .. } => true,
+            _ => false,
+        }
+    }
+
+    // Fail the consumer with the given error. If the consumer is currently
+    // busy, the error is stored until it is ready for more data.
+    fn fail(&mut self, err: Error, executor: &Executor) {
+        match mem::replace(self, Reading::Closed) {
+            Reading::New { tx } | Reading::Reading { tx } => {
+                executor.execute(move || tx.fail(err));
+            }
+            Reading::Waiting => *self = Reading::Failed { err: err },
+            _ => {}
+        }
+    }
+
+    fn take_err(&mut self) -> Option<Error> {
+        match mem::replace(self, Reading::Closed) {
+            Reading::Failed { err } => Some(err),
+            state => {
+                *self = state;
+                None
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        mem::replace(self, Reading::Closed);
+    }
+}
+
+enum Writing {
+    New { rx: core::Stream<(Bytes, SocketAddr)> },
+    // Waiting on the producer for the next datagram
+    Waiting,
+    // Waiting for the socket to become writable to send the datagram. Without
+    // `rx`, the datagram is the last one to be sent.
+    Writing { buf: Box<Buf+'static>, addr: SocketAddr, rx: Option<core::Stream<(Bytes, SocketAddr)>> },
+    // Sending stopped while waiting on the producer, the next datagram will
+    // be discarded
+    Aborted,
+    Closed,
+}
+
+impl Writing {
+    fn new_to_waiting(&mut self) -> core::Stream<(Bytes, SocketAddr)> {
+        match mem::replace(self, Writing::Waiting) {
+            Writing::New { rx } => rx,
+            _ => panic!("unexpected state"),
+        }
+    }
+
+    // A datagram has been received from the producer. If sending has been
+    // aborted, dropping `rx` lets the producer know.
+    fn push(&mut self, (bytes, addr): (Bytes, SocketAddr), rx: core::Stream<(Bytes, SocketAddr)>) {
+        match mem::replace(self, Writing::Closed) {
+            Writing::Waiting => self.waiting_to_writing(contiguous(bytes), addr, Some(rx)),
+            Writing::Aborted => debug!("Writing::push; discarding datagram"),
+            _ => panic!("unexpected state"),
+        }
+    }
+
+    fn waiting_to_writing(&mut self, buf: Box<Buf+'static>, addr: SocketAddr, rx: Option<core::Stream<(Bytes, SocketAddr)>>) {
+        *self = Writing::Writing { buf: buf, addr: addr, rx: rx };
+    }
+
+    fn writing_to_waiting(&mut self) -> (Box<Buf+'static>, SocketAddr, Option<core::Stream<(Bytes, SocketAddr)>>) {
+        match mem::replace(self, Writing::Waiting) {
+            Writing::Writing { buf, addr, rx } => (buf, addr, rx),
+            _ => panic!("unexpected state"),
+        }
+    }
+
+    fn is_writing(&self) -> bool {
+        match *self {
+            Writing::Writing { .. } => true,
+            _ => false,
+        }
+    }
+
+    // Stop sending. If the producer is currently busy, the datagram it sends
+    // next is discarded.
+    fn abort(&mut self) {
+        match mem::replace(self, Writing::Closed) {
+            Writing::Waiting => *self = Writing::Aborted,
+            _ => {}
+        }
+    }
+
+    // Send the datagram that is waiting for the socket to become writable,
+    // but accept no more from the producer. Dropping `rx` lets the producer
+    // know.
+    fn flush(&mut self) {
+        match mem::replace(self, Writing::Closed) {
+            Writing::Waiting => *self = Writing::Aborted,
+            Writing::Writing { buf, addr, .. } => self.waiting_to_writing(buf, addr, None),
+            state => *self = state,
+        }
+    }
+
+    fn close(&mut self) {
+        mem::replace(self, Writing::Closed);
+    }
+}
+
+// A datagram is sent with a single call, so its bytes must be in one slice
+fn contiguous(bytes: Bytes) -> Box<Buf+'static> {
+    let mut buf = bytes.buf();
+
+    if buf.bytes().len() == buf.remaining() {
+        return buf;
+    }
+
+    let mut data = vec![0; buf.remaining()];
+    buf.read_slice(&mut data);
+
+    Box::new(io::Cursor::new(data))
+}
+
+impl fmt::Debug for Udp {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "eventual_io::Udp {{ ... }}")
+    }
+}
@@ -0,0 +1,92 @@
+use core::{Bytes, Pair};
+use libc;
+use mio::NonBlock;
+use mio::unix::{self, UnixListener};
+use net::Connection;
+use std::{fmt, fs, io};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use sys;
+
+/// Credentials of the process at the other end of a Unix socket, as they
+/// were when the connection was established
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pid: libc::pid_t,
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+}
+
+impl Credentials {
+    pub fn pid(&self) -> libc::pid_t {
+        self.pid
+    }
+
+    pub fn uid(&self) -> libc::uid_t {
+        self.uid
+    }
+
+    pub fn gid(&self) -> libc::gid_t {
+        self.gid
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Credentials")
+            .field("pid", &self.pid)
+            .field("uid", &self.uid)
+            .field("gid", &self.gid)
+            .finish()
+    }
+}
+
+// Bind a listener to the given path. If the path is taken by a socket file
+// left behind by a process that is no longer listening, the file is removed
+// and binding is retried, while a socket that still accepts connections is
+// left alone and binding fails. Checking for a listener connects to the
+// socket, so a live listener sees a connection that is closed right away.
+pub fn bind(path: &Path) -> io::Result<NonBlock<UnixListener>> {
+    match unix::bind(path) {
+        Err(ref e) if e.raw_os_error() == Some(libc::EADDRINUSE) => {}
+        res => return res,
+    }
+
+    let meta = try!(fs::symlink_metadata(path));
+
+    if !meta.file_type().is_socket() || is_listening(path) {
+        return Err(io::Error::from_raw_os_error(libc::EADDRINUSE));
+    }
+
+    debug!("unix::bind; removing stale socket; path={:?}", path);
+    try!(fs::remove_file(path));
+
+    unix::bind(path)
+}
+
+// The device and inode of the file at the given path, used to tell whether a
+// socket file has been replaced since it was bound
+pub fn file_id(path: &Path) -> io::Result<(u64, u64)> {
+    let meta = try!(fs::symlink_metadata(path));
+    Ok((meta.dev(), meta.ino()))
+}
+
+// Whether a process accepts connections on the socket at the given path. A
+// connect to a Unix socket does not block, a full backlog is reported as
+// `EAGAIN`.
+fn is_listening(path: &Path) -> bool {
+    match unix::connect(path) {
+        Ok(..) => true,
+        Err(ref e) if e.raw_os_error() == Some(libc::EAGAIN) => true,
+        Err(..) => false,
+    }
+}
+
+// Wrap the pair of a Unix stream along with its peer's credentials
+pub fn connection<S: AsRawFd + ?Sized>(sock: &S, pair: Pair<Bytes>) -> Connection {
+    let cred = sys::peer_cred(sock).ok()
+        .map(|(pid, uid, gid)| Credentials { pid: pid, uid: uid, gid: gid });
+
+    Connection::new(pair, None, None, cred)
+}
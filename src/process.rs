@@ -0,0 +1,103 @@
+use core::{self, Bytes, Complete, Error, Pair, Sender};
+use signal;
+use std::{fmt, io, process};
+use std::process::ExitStatus;
+use sys;
+
+/// A child process spawned by the reactor, see `Reactor::spawn`
+pub struct Child {
+    id: u32,
+    stdin: Sender<Bytes>,
+    stdout: core::Stream<Bytes>,
+    stderr: core::Stream<Bytes>,
+    status: core::Future<ExitStatus>,
+}
+
+impl Child {
+    fn new(id: u32,
+           stdin: Sender<Bytes>,
+           stdout: core::Stream<Bytes>,
+           stderr: core::Stream<Bytes>,
+           status: core::Future<ExitStatus>) -> Child {
+
+        Child {
+            id: id,
+            stdin: stdin,
+            stdout: stdout,
+            stderr: stderr,
+            status: status,
+        }
+    }
+
+    /// The OS assigned process identifier
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Returns the sender writing to the child's stdin, the streams of data
+    /// read from its stdout and stderr, and the future of its exit status.
+    /// The child sees EOF on stdin once the sender is dropped.
+    pub fn into_parts(self) -> (Sender<Bytes>, core::Stream<Bytes>, core::Stream<Bytes>, core::Future<ExitStatus>) {
+        (self.stdin, self.stdout, self.stderr, self.status)
+    }
+}
+
+impl fmt::Debug for Child {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Child")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+// Wrap the stdio streams and exit status of a spawned child
+pub fn child(id: u32,
+             (stdin, _): Pair<Bytes>,
+             (_, stdout): Pair<Bytes>,
+             (_, stderr): Pair<Bytes>,
+             status: core::Future<ExitStatus>) -> Child {
+
+    Child::new(id, stdin, stdout, stderr, status)
+}
+
+// A child process waiting to be reaped by the event loop. The `SIGCHLD`
+// handler stays installed until the child has been reaped or given up on.
+pub struct Watch {
+    child: process::Child,
+    tx: Complete<ExitStatus>,
+    _handler: signal::Handler,
+}
+
+impl Watch {
+    pub fn new(child: process::Child, tx: Complete<ExitStatus>, handler: signal::Handler) -> Watch {
+        Watch {
+            child: child,
+            tx: tx,
+            _handler: handler,
+        }
+    }
+
+    // Reap the child if it has exited
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        if try!(sys::has_exited(&self.child)) {
+            self.child.wait().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn complete(self, status: ExitStatus) {
+        self.tx.complete(status);
+    }
+
+    // The exit status will not be reported, the child keeps running
+    pub fn fail(self, err: Error) {
+        self.tx.fail(err);
+    }
+}
+
+impl fmt::Debug for Watch {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "eventual_io::Watch {{ id: {} }}", self.child.id())
+    }
+}
@@ -1,51 +1,342 @@
-use core::{self, Bytes, Pair, Sender};
-use net::{self, Action};
-use mio::{self, EventLoop, Handler, Interest, NonBlock, ReadHint, PollOpt, Token};
-use mio::tcp::{TcpListener, TcpStream};
+use core::{self, Async, Bytes, Complete, Error, Pair, Sender};
+use net::{self, Action, Connection, Io, StreamOptions};
+use mio::{self, EventLoop, EventLoopConfig, Handler, Interest, NonBlock, NotifyError, ReadHint, PollOpt, Token};
+use mio::tcp::{self, TcpListener, TcpStream};
+use mio::udp::UdpSocket;
+use mio::unix::UnixStream;
+use mio::{FromFd, PipeReader, PipeWriter};
 use mio::util::Slab;
-use std::io;
-use std::sync::Arc;
+use nix::sys::signal::SIGCHLD;
+use process::{self, Child};
+use signal::{self, Signal};
+use std::{cmp, fmt, io, mem};
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::os::unix::io::{AsRawFd, IntoRawFd};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+use sys;
+
+// How long a listener waits before accepting again after running out of
+// file descriptors or memory
+const ACCEPT_BACKOFF_MS: u64 = 100;
 
 pub struct Reactor {
     inner: Arc<Inner>,
 }
 
 impl Reactor {
+    /// Start a reactor running a single event loop, using the default
+    /// settings. See `ReactorBuilder` for configuring the reactor.
     pub fn start() -> io::Result<Reactor> {
-        use std::thread;
+        ReactorBuilder::new().start()
+    }
+
+    /// Start a reactor running `n` event loops, each on its own thread.
+    /// Sockets are spread across the event loops in a round-robin fashion,
+    /// this includes the streams accepted by a listener.
+    pub fn pool(n: usize) -> io::Result<Reactor> {
+        ReactorBuilder::new().threads(n).start()
+    }
+
+    /// Manage the given `TcpStream`. If the reactor cannot take on the
+    /// stream, because the event loop's message queue or socket slab is
+    /// full, the connection's pair fails with the error instead. The same goes
+    /// for failing to apply the socket options.
+    pub fn stream(&self, io: NonBlock<TcpStream>) -> Connection {
+        self.stream_with(io, StreamOptions::default())
+    }
+
+    /// Manage the given `TcpStream` using the given options
+    pub fn stream_with(&self, io: NonBlock<TcpStream>, options: StreamOptions) -> Connection {
+        let res = net::configure(&io, &options);
+        let (mut stream, pair) = net::Stream::of(io, &options);
+        let conn = net::connection(stream.io(), pair);
+
+        if let Err(e) = res {
+            stream.fail(Error::Io(e));
+            return conn;
+        }
 
-        let mut event_loop = try!(EventLoop::new());
-        let inner = Arc::new(Inner::new(event_loop.channel()));
-        let notify = inner.notify.clone();
+        // On failure, the stream has already failed the pair
+        self.inner.balancer.next().stream(stream);
 
-        thread::spawn(move || {
-            let mut handler = IoHandler::new(notify);
-            event_loop.run(&mut handler).unwrap();
-        });
+        conn
+    }
 
-        Ok(Reactor { inner: inner })
+    /// Establish a TCP connection to the given address. The returned future
+    /// completes once the connection has been established or fails with the
+    /// connect error.
+    pub fn connect(&self, addr: &SocketAddr) -> core::Future<Connection> {
+        self.connect_with(addr, StreamOptions::default())
     }
 
-    pub fn stream(&self, io: NonBlock<TcpStream>) -> Pair<Bytes> {
-        let (stream, pair) = net::Stream::of(io);
+    /// Establish a TCP connection to the given address, the connected stream
+    /// uses the given options.
+    pub fn connect_with(&self, addr: &SocketAddr, options: StreamOptions) -> core::Future<Connection> {
+        let io = match tcp::connect(addr) {
+            Ok((io, _)) => io,
+            Err(e) => return core::Future::error(Error::Io(e)),
+        };
 
-        if !self.inner.notify.stream(stream) {
-            panic!("[unimplemented] failed to register stream with reactor");
+        if let Err(e) = net::configure(&io, &options) {
+            return core::Future::error(Error::Io(e));
         }
 
-        pair
+        let (connect, rx) = net::Connect::of(io, options);
+        self.inner.balancer.next().connect(connect);
+
+        rx
     }
 
     /// Accept connections from the given `TcpListener`
-    pub fn accept(&self, io: NonBlock<TcpListener>) -> core::Stream<Pair<Bytes>>  {
-        let (listener, rx) = net::Listener::of(io);
+    pub fn accept(&self, io: NonBlock<TcpListener>) -> core::Stream<Connection>  {
+        self.accept_with(io, StreamOptions::default())
+    }
+
+    /// Accept connections from the given `TcpListener`, applying the given
+    /// options, including the socket options, to each accepted stream.
+    pub fn accept_with(&self, io: NonBlock<TcpListener>, options: StreamOptions) -> core::Stream<Connection> {
+        let (listener, rx) = net::Listener::of(io, options);
+        self.inner.balancer.next().accept(listener);
+
+        rx
+    }
+
+    /// Manage the given `UnixStream`. The connection carries the credentials
+    /// of the peer process instead of addresses. Failures are handled as for
+    /// `stream`.
+    pub fn stream_unix(&self, io: NonBlock<UnixStream>) -> Connection {
+        self.stream_unix_with(io, StreamOptions::default())
+    }
+
+    /// Manage the given `UnixStream` using the given options. The socket
+    /// options only apply to TCP sockets and are ignored.
+    pub fn stream_unix_with(&self, io: NonBlock<UnixStream>, options: StreamOptions) -> Connection {
+        let (stream, pair) = net::Stream::of(io, &options);
+        let conn = net::unix::connection(stream.io(), pair);
+
+        // On failure, the stream has already failed the pair
+        self.inner.balancer.next().stream(stream);
+
+        conn
+    }
+
+    /// Accept connections on a Unix socket bound to the given path. A socket
+    /// file left behind by a process that is no longer listening is removed
+    /// before binding, and the socket file is removed once the listener is
+    /// closed. Fails if the socket cannot be bound, including when another
+    /// process is listening on the path. Checking for such a process connects
+    /// to the socket, so it accepts a connection that is closed right away.
+    pub fn accept_unix<P: AsRef<Path>>(&self, path: P) -> io::Result<core::Stream<Connection>> {
+        self.accept_unix_with(path, StreamOptions::default())
+    }
+
+    /// Accept connections on a Unix socket bound to the given path, applying
+    /// the given options to each accepted stream. The socket options only
+    /// apply to TCP sockets and are ignored.
+    pub fn accept_unix_with<P: AsRef<Path>>(&self, path: P, options: StreamOptions) -> io::Result<core::Stream<Connection>> {
+        let path = path.as_ref();
+        let io = try!(net::unix::bind(path));
+
+        let (listener, rx) = net::Listener::unix(io, path.to_path_buf(), options);
+        self.inner.balancer.next().accept(listener);
+
+        Ok(rx)
+    }
+
+    /// Manage an arbitrary file descriptor, such as a pipe or an eventfd, as
+    /// a stream. Data is read and written with the same backpressure as for
+    /// sockets. Failures are handled as for `stream`.
+    pub fn register<T: Io + 'static>(&self, io: T) -> Pair<Bytes> {
+        self.register_with(io, StreamOptions::default())
+    }
+
+    /// Manage an arbitrary file descriptor as a stream using the given
+    /// options. The socket options are ignored.
+    pub fn register_with<T: Io + 'static>(&self, io: T, options: StreamOptions) -> Pair<Bytes> {
+        let (stream, pair) = net::Stream::of(io, &options);
+
+        // On failure, the stream has already failed the pair
+        self.inner.balancer.next().stream(stream);
+
+        pair
+    }
+
+    /// Spawn a child process with its stdin, stdout and stderr connected to
+    /// the reactor through non-blocking pipes, replacing the command's stdio
+    /// configuration. The child's exit status is reported once the event
+    /// loop receives `SIGCHLD`, for which the reactor installs its own
+    /// handler until the child has been reaped.
+    pub fn spawn(&self, mut cmd: Command) -> io::Result<Child> {
+        // Installed before the child exists, so that its exit is noticed. An
+        // exit that happens before the event loop receives the child is
+        // picked up when the event loop first tries to reap it.
+        let handler = try!(signal::install(SIGCHLD));
+
+        let mut child = try!(cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn());
+
+        let (stdin, stdout, stderr) = match pipes(&mut child) {
+            Ok(pipes) => pipes,
+            Err(e) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(e);
+            }
+        };
+
+        let id = child.id();
+        let (tx, status) = core::Future::pair();
+
+        // Children are reaped by the first event loop, which receives
+        // `SIGCHLD`. On failure, the status future has already failed.
+        self.inner.balancer.loops[0].child(process::Watch::new(child, tx, handler));
+
+        Ok(process::child(
+            id,
+            self.register(stdin),
+            self.register(stdout),
+            self.register(stderr),
+            status))
+    }
+
+    /// Returns a stream of the given signals as they are delivered to the
+    /// process, starting with those delivered once this returns. The reactor
+    /// installs its own handler for each of the signals, so for instance
+    /// `SIGINT` no longer terminates the process, until the stream is dropped
+    /// or the reactor shuts down. The previous disposition is then restored,
+    /// unless another stream still receives the signal. While the consumer is
+    /// busy, further signals are held back, each signal at most once.
+    pub fn signals(&self, signals: &[Signal]) -> core::Stream<Signal> {
+        let (tx, rx) = core::Stream::pair();
 
-        if !self.inner.notify.accept(listener) {
-            panic!("[unimplemented] failed to register listener with reactor");
+        let subscription = match signal::Subscription::new(signals) {
+            Ok(subscription) => subscription,
+            Err(e) => {
+                tx.fail(Error::Io(e));
+                return rx;
+            }
+        };
+
+        // On failure, the stream has already failed
+        self.inner.balancer.next().signals(subscription, tx);
+
+        rx
+    }
+
+    /// Manage the given `UdpSocket`. Received datagrams are yielded along
+    /// with the address they came from, and datagrams sent on the returned
+    /// sender go to the address they are paired with. As with streams, the
+    /// socket is only read from while the consumer is ready for the next
+    /// datagram, so a slow consumer leaves datagrams in the kernel's buffer,
+    /// where they are dropped once it is full.
+    pub fn udp(&self, io: NonBlock<UdpSocket>) -> Pair<(Bytes, SocketAddr)> {
+        self.udp_with(io, StreamOptions::default())
+    }
+
+    /// Manage the given `UdpSocket` using the given options. Timeouts do not
+    /// apply to datagram sockets and the socket options must not include TCP
+    /// specific ones. A datagram larger than the read buffers of the buffer
+    /// strategy is truncated. A datagram that cannot be sent is dropped and
+    /// the error fails the receiving stream, while sending carries on with
+    /// the next datagram.
+    pub fn udp_with(&self, io: NonBlock<UdpSocket>, options: StreamOptions) -> Pair<(Bytes, SocketAddr)> {
+        let res = net::configure(&io, &options);
+        let (mut udp, pair) = net::Udp::of(io, &options);
+
+        if let Err(e) = res {
+            udp.fail(Error::Io(e));
+            return pair;
         }
 
+        // On failure, the socket has already failed the pair
+        self.inner.balancer.next().udp(udp);
+
+        pair
+    }
+
+    /// Returns a future that completes once the given duration has elapsed.
+    /// The timeout is tracked by the event loop's timer, so the resolution is
+    /// limited to the timer's tick. The future never completes early, but may
+    /// complete up to a tick late.
+    pub fn timeout(&self, dur: Duration) -> core::Future<()> {
+        let (tx, rx) = core::Future::pair();
+        self.inner.balancer.next().timeout(tx, millis(dur));
+
+        rx
+    }
+
+    /// Returns a stream that yields a value every time the given duration
+    /// elapses. The next tick is only scheduled once the consumer is ready
+    /// for it, so a slow consumer does not cause ticks to pile up.
+    pub fn interval(&self, dur: Duration) -> core::Stream<()> {
+        let (tx, rx) = core::Stream::pair();
+        self.inner.balancer.next().interval(tx, millis(dur));
+
         rx
     }
+
+    /// Number of messages, such as new sockets or consumers being ready for
+    /// more data, that the event loops have yet to process. This includes
+    /// the messages held back because an event loop's queue was full.
+    pub fn queue_depth(&self) -> usize {
+        self.inner.balancer.loops.iter()
+            .map(|notify| notify.depth())
+            .fold(0, |sum, depth| sum + depth)
+    }
+
+    /// Largest number of messages that an event loop has held back at once
+    /// because its queue was full. Messages about existing sockets are held
+    /// back without limit, so a growing high-water mark means that the event
+    /// loops cannot keep up.
+    pub fn overflow_high_water(&self) -> usize {
+        self.inner.balancer.loops.iter()
+            .map(|notify| notify.overflow.high_water())
+            .fold(0, cmp::max)
+    }
+
+    /// Shut down the reactor, flushing data that has already been handed to
+    /// the reactor for writing. See `shutdown_with`.
+    pub fn shutdown(&self) -> core::Future<()> {
+        self.shutdown_with(ShutdownPolicy::Flush)
+    }
+
+    /// Shut down the reactor. Listeners are closed, while pending connects,
+    /// timers and stream consumers fail with `Error::Shutdown`. Pending writes
+    /// are handled according to `policy`. The returned future completes once
+    /// all event loop threads have exited.
+    ///
+    /// Dropping the last handle to the reactor shuts it down using
+    /// `ShutdownPolicy::Flush`.
+    pub fn shutdown_with(&self, policy: ShutdownPolicy) -> core::Future<()> {
+        let exited: Vec<_> = self.inner.balancer.loops.iter()
+            .map(|notify| {
+                let (tx, rx) = core::Future::pair();
+                notify.shutdown(Some(tx), policy);
+                rx
+            })
+            .collect();
+
+        core::async::join(exited).map(|_| ())
+    }
+}
+
+/// How pending writes are handled when the reactor shuts down
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShutdownPolicy {
+    /// Finish writing data that the reactor has already received from the
+    /// producer before closing the socket
+    Flush,
+    /// Close sockets right away, dropping any pending data
+    Discard,
 }
 
 impl Clone for Reactor {
@@ -55,12 +346,212 @@ impl Clone for Reactor {
 }
 
 struct Inner {
-    notify: Notify,
+    balancer: Arc<Balancer>,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        // The last handle is gone, nothing else can be submitted to the
+        // reactor.
+        for notify in &self.balancer.loops {
+            notify.shutdown(None, ShutdownPolicy::Flush);
+        }
+    }
+}
+
+/// Configures and starts a reactor
+pub struct ReactorBuilder {
+    threads: usize,
+    max_sockets: usize,
+    config: EventLoopConfig,
+    thread_name: Option<String>,
+    affinity: Option<Arc<Fn(usize) + Send + Sync>>,
+}
+
+impl ReactorBuilder {
+    pub fn new() -> ReactorBuilder {
+        ReactorBuilder {
+            threads: 1,
+            max_sockets: 65_535,
+            config: EventLoopConfig::default(),
+            thread_name: None,
+            affinity: None,
+        }
+    }
+
+    /// Number of event loops, each running on its own thread. Defaults to 1.
+    pub fn threads(mut self, n: usize) -> ReactorBuilder {
+        self.threads = cmp::max(n, 1);
+        self
+    }
+
+    /// Maximum number of sockets registered with each event loop. Defaults
+    /// to 65,535.
+    pub fn max_sockets(mut self, n: usize) -> ReactorBuilder {
+        self.max_sockets = cmp::max(n, 1);
+        self
+    }
+
+    /// Maximum number of messages, such as new sockets or consumers being
+    /// ready for more data, that can be queued for an event loop. Defaults
+    /// to 4,096. Messages that do not fit are held in an overflow queue. Once
+    /// that also holds this many messages, new sockets and timers fail until
+    /// the event loop catches up. The minimum is 1.
+    pub fn notify_capacity(mut self, n: usize) -> ReactorBuilder {
+        self.config.notify_capacity = cmp::max(n, 1);
+        self
+    }
+
+    /// Resolution of the timer used for timeouts and intervals. Defaults to
+    /// 100ms.
+    pub fn timer_tick(mut self, dur: Duration) -> ReactorBuilder {
+        self.config.timer_tick_ms = cmp::max(millis(dur), 1);
+        self
+    }
+
+    /// Number of slots in the timer wheel. Defaults to 1,024, the minimum is
+    /// 1.
+    pub fn timer_wheel_size(mut self, n: usize) -> ReactorBuilder {
+        self.config.timer_wheel_size = cmp::max(n, 1);
+        self
+    }
+
+    /// Maximum number of pending timers, including the streams' timeouts,
+    /// per event loop. Defaults to 65,536, the minimum is 1.
+    pub fn timer_capacity(mut self, n: usize) -> ReactorBuilder {
+        self.config.timer_capacity = cmp::max(n, 1);
+        self
+    }
+
+    /// Maximum number of queued messages processed per event loop tick,
+    /// between polling for IO events. The number of IO events per poll is
+    /// fixed by mio. Defaults to 256, the minimum is 1.
+    pub fn messages_per_tick(mut self, n: usize) -> ReactorBuilder {
+        self.config.messages_per_tick = cmp::max(n, 1);
+        self
+    }
+
+    /// Name of the event loop threads. With more than one thread, the index
+    /// of the event loop is appended.
+    pub fn thread_name<S: Into<String>>(mut self, name: S) -> ReactorBuilder {
+        self.thread_name = Some(name.into());
+        self
+    }
+
+    /// Called on each event loop thread with the index of the event loop,
+    /// before the event loop starts running. Use this to pin the thread to a
+    /// CPU.
+    pub fn affinity<F>(mut self, f: F) -> ReactorBuilder
+            where F: Fn(usize) + Send + Sync + 'static {
+        self.affinity = Some(Arc::new(f));
+        self
+    }
+
+    /// Start the reactor
+    pub fn start(self) -> io::Result<Reactor> {
+        let mut event_loops = vec![];
+
+        for _ in 0..self.threads {
+            event_loops.push(try!(EventLoop::configured(self.config)));
+        }
+
+        let loops = event_loops.iter()
+            .map(|event_loop| Notify::new(event_loop.channel(), self.config.notify_capacity))
+            .collect();
+
+        let balancer = Arc::new(Balancer::new(loops));
+
+        for (id, event_loop) in event_loops.into_iter().enumerate() {
+            let mut thread = thread::Builder::new();
+
+            if let Some(ref name) = self.thread_name {
+                if self.threads == 1 {
+                    thread = thread.name(name.clone());
+                } else {
+                    thread = thread.name(format!("{}-{}", name, id));
+                }
+            }
+
+            let affinity = self.affinity.clone();
+            let max_sockets = self.max_sockets;
+            let max_timers = self.config.timer_capacity;
+            let tick_ms = self.config.timer_tick_ms;
+
+            let res = {
+                let balancer = balancer.clone();
+
+                thread.spawn(move || {
+                    if let Some(affinity) = affinity {
+                        affinity(id);
+                    }
+
+                    let handler = IoHandler::new(id, balancer, max_sockets, max_timers, tick_ms);
+                    run(event_loop, handler);
+                })
+            };
+
+            if let Err(e) = res {
+                // Stop the event loops that are already running, the ones
+                // that have not been started are dropped
+                for notify in &balancer.loops[..id] {
+                    notify.shutdown(None, ShutdownPolicy::Discard);
+                }
+
+                return Err(e);
+            }
+        }
+
+        Ok(Reactor { inner: Arc::new(Inner { balancer: balancer }) })
+    }
+}
+
+impl fmt::Debug for ReactorBuilder {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ReactorBuilder")
+            .field("threads", &self.threads)
+            .field("max_sockets", &self.max_sockets)
+            .field("config", &self.config)
+            .field("thread_name", &self.thread_name)
+            .finish()
+    }
 }
 
-impl Inner {
-    fn new(sender: mio::Sender<Message>) -> Inner {
-        Inner { notify: Notify::new(sender) }
+// Runs the event loop on the current thread until it is shut down
+fn run(mut event_loop: EventLoop<IoHandler>, mut handler: IoHandler) {
+    if let Err(e) = event_loop.run(&mut handler) {
+        error!("Reactor; event loop failed; err={:?}", e);
+    }
+
+    handler.exit(&mut event_loop);
+}
+
+/*
+ *
+ * ===== Balancer =====
+ *
+ */
+
+// Spreads sockets across the reactor's event loops, round-robin
+struct Balancer {
+    loops: Vec<Notify>,
+    next: AtomicUsize,
+}
+
+impl Balancer {
+    fn new(loops: Vec<Notify>) -> Balancer {
+        Balancer {
+            loops: loops,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    // Returns the id of the event loop that receives the next socket
+    fn next_id(&self) -> usize {
+        self.next.fetch_add(1, Ordering::Relaxed) % self.loops.len()
+    }
+
+    fn next(&self) -> &Notify {
+        &self.loops[self.next_id()]
     }
 }
 
@@ -74,45 +565,375 @@ impl Inner {
 pub enum Message {
     Stream(net::Stream),
     Accept(net::Listener),
-    AcceptInterest(Option<Sender<Pair<Bytes>>>, Token),
+    Connect(net::Connect),
+    Timeout(Complete<()>, u64),
+    Interval(Sender<()>, u64),
+    AcceptInterest(Option<Sender<Connection>>, Token),
     ReadInterest(Option<Sender<Bytes>>, Token),
     WriteInterest(Option<(Bytes, core::Stream<Bytes>)>, Token),
+    WriteFailed(Error, Token),
+    Udp(net::Udp),
+    UdpReadInterest(Option<Sender<(Bytes, SocketAddr)>>, Token),
+    UdpWriteInterest(Option<((Bytes, SocketAddr), core::Stream<(Bytes, SocketAddr)>)>, Token),
+    UdpWriteFailed(Error, Token),
+    Shutdown(Option<Complete<()>>, ShutdownPolicy),
+    // Wakes up the event loop to process the overflow queue
+    Drain,
+    Child(process::Watch),
+    Signals(signal::Subscription, Sender<Signal>),
+    SignalInterest(Option<Sender<Signal>>, usize),
+}
+
+impl Message {
+    // The reactor will not process the message, either because it has shut
+    // down or because the message could not be queued. Let the consumer know.
+    fn fail(self, err: Error) {
+        match self {
+            Message::Stream(mut stream) => {
+                stream.fail(err);
+            }
+            Message::Accept(mut listener) => {
+                listener.abort(err);
+            }
+            Message::Connect(connect) => {
+                connect.fail(err);
+            }
+            Message::Timeout(tx, _) => {
+                tx.fail(err);
+            }
+            Message::Interval(tx, _) => {
+                tx.fail(err);
+            }
+            Message::AcceptInterest(Some(tx), _) => {
+                tx.fail(err);
+            }
+            Message::ReadInterest(Some(tx), _) => {
+                tx.fail(err);
+            }
+            Message::Udp(mut udp) => {
+                udp.fail(err);
+            }
+            Message::UdpReadInterest(Some(tx), _) => {
+                tx.fail(err);
+            }
+            Message::Child(watch) => {
+                watch.fail(err);
+            }
+            Message::Signals(_, tx) => {
+                tx.fail(err);
+            }
+            Message::SignalInterest(Some(tx), _) => {
+                tx.fail(err);
+            }
+            Message::Shutdown(Some(tx), _) => {
+                match err {
+                    // Already shut down
+                    Error::Shutdown => tx.complete(()),
+                    err => tx.fail(err),
+                }
+            }
+            // Dropping the producer's stream lets it know that the write half
+            // is closed.
+            _ => {}
+        }
+    }
+
+    // Whether the message submits new work to the reactor, as opposed to
+    // being about a socket the reactor already manages
+    fn is_new(&self) -> bool {
+        match *self {
+            Message::Stream(..) |
+            Message::Accept(..) |
+            Message::Connect(..) |
+            Message::Timeout(..) |
+            Message::Interval(..) |
+            Message::Udp(..) |
+            Message::Child(..) |
+            Message::Signals(..) => true,
+            _ => false,
+        }
+    }
 }
 
 pub struct Notify {
     sender: mio::Sender<Message>,
+    // Cleared once the event loop no longer processes messages
+    running: Arc<RwLock<bool>>,
+    // Messages that did not fit in the event loop's queue
+    overflow: Arc<Overflow>,
+    // Number of messages that have not been processed yet
+    depth: Arc<AtomicUsize>,
 }
 
 impl Notify {
-    fn new(sender: mio::Sender<Message>) -> Notify {
-        Notify { sender: sender }
+    fn new(sender: mio::Sender<Message>, overflow_capacity: usize) -> Notify {
+        Notify {
+            sender: sender,
+            running: Arc::new(RwLock::new(true)),
+            overflow: Arc::new(Overflow::new(overflow_capacity)),
+            depth: Arc::new(AtomicUsize::new(0)),
+        }
     }
 
     pub fn stream(&self, stream: net::Stream) -> bool {
-        self.sender.send(Message::Stream(stream)).is_ok()
+        self.send(Message::Stream(stream))
     }
 
     pub fn accept(&self, listener: net::Listener) -> bool {
-        self.sender.send(Message::Accept(listener)).is_ok()
+        self.send(Message::Accept(listener))
+    }
+
+    pub fn connect(&self, connect: net::Connect) -> bool {
+        self.send(Message::Connect(connect))
+    }
+
+    pub fn timeout(&self, tx: Complete<()>, ms: u64) -> bool {
+        self.send(Message::Timeout(tx, ms))
+    }
+
+    pub fn interval(&self, tx: Sender<()>, ms: u64) -> bool {
+        self.send(Message::Interval(tx, ms))
+    }
+
+    pub fn udp(&self, udp: net::Udp) -> bool {
+        self.send(Message::Udp(udp))
+    }
+
+    pub fn child(&self, watch: process::Watch) -> bool {
+        self.send(Message::Child(watch))
+    }
+
+    pub fn signals(&self, subscription: signal::Subscription, tx: Sender<Signal>) -> bool {
+        self.send(Message::Signals(subscription, tx))
+    }
+
+    pub fn accept_interest(&self, tx: Option<Sender<Connection>>, token: Token) {
+        self.send_interest(Message::AcceptInterest(tx, token));
+    }
+
+    pub fn stream_read_ready(&self, tx: Option<Sender<Bytes>>, token: Token) {
+        self.send_interest(Message::ReadInterest(tx, token));
+    }
+
+    pub fn stream_write_ready(&self, rx: Option<(Bytes, core::Stream<Bytes>)>, token: Token) {
+        self.send_interest(Message::WriteInterest(rx, token));
+    }
+
+    pub fn stream_write_failed(&self, err: Error, token: Token) {
+        self.send_interest(Message::WriteFailed(err, token));
+    }
+
+    pub fn udp_read_ready(&self, tx: Option<Sender<(Bytes, SocketAddr)>>, token: Token) {
+        self.send_interest(Message::UdpReadInterest(tx, token));
+    }
+
+    pub fn udp_write_ready(&self, rx: Option<((Bytes, SocketAddr), core::Stream<(Bytes, SocketAddr)>)>, token: Token) {
+        self.send_interest(Message::UdpWriteInterest(rx, token));
+    }
+
+    pub fn udp_write_failed(&self, err: Error, token: Token) {
+        self.send_interest(Message::UdpWriteFailed(err, token));
     }
 
-    pub fn accept_interest(&self, tx: Option<Sender<Pair<Bytes>>>, token: Token) -> bool {
-        self.sender.send(Message::AcceptInterest(tx, token))
-            .is_ok()
+    pub fn signal_interest(&self, tx: Option<Sender<Signal>>, id: usize) {
+        self.send_interest(Message::SignalInterest(tx, id));
     }
 
-    pub fn stream_read_ready(&self, tx: Option<Sender<Bytes>>, token: Token) -> bool {
-        self.sender.send(Message::ReadInterest(tx, token)).is_ok()
+    pub fn shutdown(&self, tx: Option<Complete<()>>, policy: ShutdownPolicy) {
+        self.send(Message::Shutdown(tx, policy));
     }
 
-    pub fn stream_write_ready(&self, rx: Option<(Bytes, core::Stream<Bytes>)>, token: Token) -> bool {
-        self.sender.send(Message::WriteInterest(rx, token)).is_ok()
+    // Number of messages that the event loop has yet to process
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    // Once the event loop has exited, messages are failed right away instead
+    // of being left in the queue forever. The lock ensures that no message is
+    // queued after the remaining ones have been drained. New work that does
+    // not fit in the queues is failed as well, but the caller is told about
+    // it.
+    fn send(&self, msg: Message) -> bool {
+        let (msg, err, ret) = {
+            let running = self.running.read().unwrap();
+
+            if !*running {
+                (msg, Error::Shutdown, true)
+            } else {
+                match self.enqueue(msg) {
+                    Ok(()) => return true,
+                    Err(msg) => (msg, queue_full(), false),
+                }
+            }
+        };
+
+        // Failing the message may run consumer callbacks, so do it without
+        // holding the lock.
+        msg.fail(err);
+        ret
+    }
+
+    // Messages about a socket that the event loop already manages are never
+    // refused, as the socket waits on them. Refusing one would leave the
+    // socket, along with its slab slot, stuck forever. Once the event loop
+    // has exited, the message is failed, the socket is closed by then.
+    fn send_interest(&self, msg: Message) {
+        debug_assert!(!msg.is_new());
+        self.send(msg);
+    }
+
+    // Queue the message for the event loop. Once the event loop's queue is
+    // full, messages go to the overflow queue until it has been drained, so
+    // they are still processed in order.
+    fn enqueue(&self, msg: Message) -> Result<(), Message> {
+        // Counted before the message can be processed
+        self.depth.fetch_add(1, Ordering::Relaxed);
+
+        let msg = if self.overflow.is_empty() {
+            match self.sender.send(msg) {
+                Ok(()) => return Ok(()),
+                Err(NotifyError::Full(msg)) => msg,
+                Err(NotifyError::Io(e)) => {
+                    // The message has been queued, but the event loop could
+                    // not be woken up
+                    error!("Notify::enqueue; failed to wake up event loop; err={:?}", e);
+                    return Ok(());
+                }
+            }
+        } else {
+            msg
+        };
+
+        if let Err(msg) = self.overflow.push(msg) {
+            self.depth.fetch_sub(1, Ordering::Relaxed);
+            return Err(msg);
+        }
+
+        // The overflow queue is drained whenever the event loop processes a
+        // message. If the event loop's queue is full, there are plenty of
+        // those coming.
+        if let Err(NotifyError::Io(e)) = self.sender.send(Message::Drain) {
+            error!("Notify::enqueue; failed to wake up event loop; err={:?}", e);
+        }
+
+        Ok(())
+    }
+
+    // Called by the event loop for each message taken off either queue
+    fn processed(&self) {
+        self.depth.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn close(&self) {
+        *self.running.write().unwrap() = false;
     }
 }
 
 impl Clone for Notify {
     fn clone(&self) -> Notify {
-        Notify { sender: self.sender.clone() }
+        Notify {
+            sender: self.sender.clone(),
+            running: self.running.clone(),
+            overflow: self.overflow.clone(),
+            depth: self.depth.clone(),
+        }
+    }
+}
+
+// Holds the messages that did not fit in the event loop's queue
+struct Overflow {
+    queue: Mutex<VecDeque<Message>>,
+    // Length of the queue, so that senders can check it without locking
+    len: AtomicUsize,
+    // Largest length the queue has reached
+    high_water: AtomicUsize,
+    // Maximum number of messages before new work is refused. Messages about
+    // existing sockets are always accepted, there are only a few per socket.
+    capacity: usize,
+}
+
+impl Overflow {
+    fn new(capacity: usize) -> Overflow {
+        Overflow {
+            queue: Mutex::new(VecDeque::new()),
+            len: AtomicUsize::new(0),
+            high_water: AtomicUsize::new(0),
+            capacity: capacity,
+        }
+    }
+
+    fn push(&self, msg: Message) -> Result<(), Message> {
+        let mut queue = self.queue.lock().unwrap();
+
+        if msg.is_new() && queue.len() >= self.capacity {
+            return Err(msg);
+        }
+
+        queue.push_back(msg);
+
+        let len = queue.len();
+        self.len.store(len, Ordering::SeqCst);
+
+        if len > self.high_water.load(Ordering::Relaxed) {
+            self.high_water.store(len, Ordering::Relaxed);
+
+            // Past the capacity, only messages about existing sockets are
+            // queued. Warn each time the queue doubles in size.
+            if len > self.capacity && len.is_power_of_two() {
+                warn!("Notify::enqueue; event loop is falling behind; overflow={}", len);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<Message> {
+        let mut queue = self.queue.lock().unwrap();
+        let msg = queue.pop_front();
+        self.len.store(queue.len(), Ordering::SeqCst);
+        msg
+    }
+
+    fn len(&self) -> usize {
+        self.len.load(Ordering::SeqCst)
+    }
+
+    fn high_water(&self) -> usize {
+        self.high_water.load(Ordering::Relaxed)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/*
+ *
+ * ===== Timeout =====
+ *
+ */
+
+pub enum Timeout {
+    // Resume accepting sockets on the listener
+    Accept(Token),
+    // A user timer, the token references the `timers` slab
+    Timer(Token),
+    // A stream's idle, read or write timeout
+    Stream(Token, net::Timeout),
+}
+
+// Pending user timers
+enum Timer {
+    Timeout(Complete<()>),
+    Interval(Sender<()>, u64),
+}
+
+impl Timer {
+    fn fail(self, err: Error) {
+        match self {
+            Timer::Timeout(tx) => tx.fail(err),
+            Timer::Interval(tx, _) => tx.fail(err),
+        }
     }
 }
 
@@ -122,16 +943,46 @@ impl Clone for Notify {
  *
  */
 
-struct IoHandler {
+pub struct IoHandler {
     conns: Slab<net::Evented>,
+    timers: Slab<Timer>,
     notify: Notify,
+    // Index of the event loop in the balancer
+    id: usize,
+    balancer: Arc<Balancer>,
+    // Set once a shutdown has been requested
+    shutdown: Option<ShutdownPolicy>,
+    // Completed once the event loop has exited
+    waiters: Vec<Complete<()>>,
+    // Slab token of the signal receiver used to reap children, once created
+    signals: Option<Token>,
+    // Children whose exit status has yet to be reported
+    children: Vec<process::Watch>,
+    // Consumers of signals, by the slab token of their receiver
+    subscribers: HashMap<usize, signal::Subscriber>,
+    // Resolution of the event loop's timer
+    tick_ms: u64,
 }
 
 impl IoHandler {
-    fn new(notify: Notify) -> IoHandler {
+    fn new(id: usize,
+           balancer: Arc<Balancer>,
+           max_sockets: usize,
+           max_timers: usize,
+           tick_ms: u64) -> IoHandler {
+
         IoHandler {
-            conns: Slab::new(65_535),
-            notify: notify,
+            conns: Slab::new(max_sockets),
+            timers: Slab::new(max_timers),
+            notify: balancer.loops[id].clone(),
+            id: id,
+            balancer: balancer,
+            shutdown: None,
+            waiters: vec![],
+            signals: None,
+            children: vec![],
+            subscribers: HashMap::new(),
+            tick_ms: tick_ms,
         }
     }
 }
@@ -153,7 +1004,7 @@ impl IoHandler {
     // any further values.
     fn accept_interest(&mut self,
                        event_loop: &mut EventLoop<IoHandler>,
-                       tx: Option<Sender<Pair<Bytes>>>,
+                       tx: Option<Sender<Connection>>,
                        token: Token) {
 
         // If `tx` has a value, then the consumer is ready to accept a new
@@ -184,6 +1035,9 @@ impl IoHandler {
             Action::Register(..) => {
                 self.listener_register(event_loop, token);
             }
+            Action::Backoff => {
+                self.listener_backoff(event_loop, token);
+            }
             Action::Remove => {
                 debug!("Reactor::handle_listener_action; closing listener socket");
                 self.conns.remove(token);
@@ -196,33 +1050,147 @@ impl IoHandler {
     fn listener_register(&mut self, event_loop: &mut EventLoop<IoHandler>, token: Token) {
         debug!("Reactor::listener_register; registering event loop interest");
 
-        let res = event_loop.register_opt(
-            self.conns[token].listener().io(),
+        let res = register(
+            event_loop,
+            &mut self.conns[token],
             token,
-            Interest::readable(),
-            PollOpt::edge() | PollOpt::oneshot());
+            Interest::readable());
 
-        if let Err(_) = res {
-            panic!("[unimplemented] failed to register interest with event loop");
+        if let Err(e) = res {
+            debug!("Reactor::listener_register; failed to register; err={:?}", e);
+            let action = self.conns[token].listener().fail(e);
+            self.handle_listener_action(action, event_loop, token);
         }
     }
 
-    fn accept(&mut self, event_loop: &mut EventLoop<IoHandler>, token: Token) {
-        let mut reregister;
+    // Wait a bit before registering the listener's socket again
+    fn listener_backoff(&mut self, event_loop: &mut EventLoop<IoHandler>, token: Token) {
+        debug!("Reactor::listener_backoff; pausing accept for {}ms", ACCEPT_BACKOFF_MS);
 
+        if let Err(_) = event_loop.timeout_ms(Timeout::Accept(token), ACCEPT_BACKOFF_MS) {
+            // The timer is at capacity, there is nothing better to do than
+            // to try again right away.
+            self.listener_register(event_loop, token);
+        }
+    }
+
+    fn accept(&mut self, event_loop: &mut EventLoop<IoHandler>, token: Token) {
         debug!("Reactor::accept; Attempting to accept socket");
 
-        // TODO: Consider looping if consumer is ready to accept another socket
-        match self.conns[token].listener().accept(&self.notify, token) {
-            Some((stream, action)) => {
-                self.stream(event_loop, stream);
-                reregister = action.is_register();
+        let mut budget = self.conns[token].listener().budget();
+
+        // Keep accepting as long as sockets are pending and the consumer is
+        // ready for them, up to the budget.
+        loop {
+            // Leave pending connections in the backlog until a slot frees up.
+            // Streams handed to another event loop are failed there if its
+            // slab is full.
+            if !self.conns.has_remaining() {
+                warn!("Reactor::accept; too many sockets, pausing accept");
+                return self.handle_listener_action(Action::backoff(), event_loop, token);
+            }
+
+            let (stream, action) = self.conns[token].listener().accept(&self.notify, token);
+            let accepted = stream.is_some();
+
+            if let Some(stream) = stream {
+                self.dispatch(event_loop, stream);
+            }
+
+            budget -= 1;
+
+            match action {
+                Action::Register(..) if accepted && budget > 0 => {}
+                action => return self.handle_listener_action(action, event_loop, token),
             }
-            None => reregister = true,
         }
+    }
 
-        if reregister {
-            self.listener_register(event_loop, token);
+    // Hand an accepted stream to the next event loop
+    fn dispatch(&mut self, event_loop: &mut EventLoop<IoHandler>, stream: net::Stream) {
+        let id = self.balancer.next_id();
+
+        if id == self.id {
+            self.stream(event_loop, stream);
+        } else if !self.balancer.loops[id].stream(stream) {
+            warn!("Reactor::dispatch; event loop {} is too busy, failed stream", id);
+        }
+    }
+
+    /*
+     *
+     * ===== Timer =====
+     *
+     */
+
+    // Schedule a user timer to fire in `ms` milliseconds
+    fn timer(&mut self, event_loop: &mut EventLoop<IoHandler>, timer: Timer, ms: u64) {
+        let token = match self.timers.insert(timer) {
+            Ok(token) => token,
+            Err(timer) => return timer.fail(too_many_timers()),
+        };
+
+        // The timer fires on the tick that the deadline falls in, which can
+        // be up to a tick early, so one more tick is added.
+        if let Err(_) = event_loop.timeout_ms(Timeout::Timer(token), ms + self.tick_ms) {
+            self.timers.remove(token).unwrap().fail(too_many_timers());
+        }
+    }
+
+    fn timer_fired(&mut self, token: Token) {
+        match self.timers.remove(token) {
+            Some(Timer::Timeout(tx)) => {
+                tx.complete(());
+            }
+            Some(Timer::Interval(tx, ms)) => {
+                let notify = self.notify.clone();
+
+                // Schedule the next tick once the consumer is ready for it
+                tx.send(()).receive(move |res| {
+                    if let Ok(tx) = res {
+                        // If the reactor is gone or too busy, the interval
+                        // fails.
+                        notify.interval(tx, ms);
+                    }
+                });
+            }
+            None => {}
+        }
+    }
+
+    /*
+     *
+     * ===== Connect =====
+     *
+     */
+
+    // Start managing a connecting socket
+    fn connect(&mut self, event_loop: &mut EventLoop<IoHandler>, connect: net::Connect) {
+        let token = match self.conns.insert(net::Evented::Connect(connect)) {
+            Ok(token) => token,
+            Err(connect) => return connect.into_connect().fail(too_many_sockets()),
+        };
+
+        // The socket becomes writable once the connect completes, successfully
+        // or not.
+        let res = register(
+            event_loop,
+            &mut self.conns[token],
+            token,
+            Interest::writable());
+
+        if let Err(e) = res {
+            debug!("Reactor::connect; failed to register; err={:?}", e);
+            self.conns.remove(token).unwrap().into_connect().fail(Error::Io(e));
+        }
+    }
+
+    fn connected(&mut self, event_loop: &mut EventLoop<IoHandler>, token: Token) {
+        let connect = self.conns.remove(token).unwrap().into_connect();
+
+        // On success, start managing the socket as a regular stream
+        if let Some(stream) = connect.connected() {
+            self.stream(event_loop, stream);
         }
     }
 
@@ -235,7 +1203,11 @@ impl IoHandler {
     fn stream(&mut self, event_loop: &mut EventLoop<IoHandler>, stream: net::Stream) {
         let token = match self.conns.insert(net::Evented::Stream(stream)) {
             Ok(token) => token,
-            Err(_) => panic!("[unimplemented] slab full - send error"),
+            Err(mut stream) => {
+                debug!("Reactor::stream; too many sockets, closing stream");
+                stream.stream().fail(too_many_sockets());
+                return;
+            }
         };
 
         let action = self.conns[token].stream().init(&self.notify, token);
@@ -258,6 +1230,13 @@ impl IoHandler {
                             event_loop: &mut EventLoop<IoHandler>,
                             token: Token) {
 
+        let action = match action {
+            // While shutting down, streams are closed as soon as they are
+            // done flushing
+            Action::Wait if self.shutdown.is_some() => Action::remove(),
+            action => action,
+        };
+
         // Process the stream action
         match action {
             Action::Register(interest) => {
@@ -265,10 +1244,31 @@ impl IoHandler {
             }
             Action::Remove => {
                 debug!("Closing stream socket");
+                self.conns[token].stream().clear_timeouts(event_loop);
                 self.conns.remove(token);
+                return;
             }
             _ => {}
         }
+
+        // The stream may have been closed while registering
+        if self.conns.contains(token) {
+            if let Err(err) = self.conns[token].stream().update_timeouts(event_loop, token) {
+                // Rather than going without its timeouts, the stream is failed
+                debug!("Reactor::handle_stream_action; failed to schedule timeout");
+                let action = self.conns[token].stream().fail(err);
+                self.handle_stream_action(action, event_loop, token);
+            }
+        }
+    }
+
+    fn stream_timeout(&mut self,
+                      event_loop: &mut EventLoop<IoHandler>,
+                      token: Token,
+                      timeout: net::Timeout) {
+
+        let action = self.conns[token].stream().timed_out(timeout);
+        self.handle_stream_action(action, event_loop, token);
     }
 
     fn read_interest(&mut self,
@@ -297,7 +1297,7 @@ impl IoHandler {
         // writes.
         match head {
             Some((bytes, rest)) => {
-                let action = self.conns[token].stream().write_interest(bytes, rest);
+                let action = self.conns[token].stream().write_interest(bytes, rest, &self.notify, token);
                 self.handle_stream_action(action, event_loop, token);
             }
             None => {
@@ -316,43 +1316,317 @@ impl IoHandler {
             net::Interest::ReadWrite => Interest::readable() | Interest::writable(),
         };
 
-        let res = event_loop.register_opt(
-            self.conns[token].stream().io(),
+        let res = register(
+            event_loop,
+            &mut self.conns[token],
             token,
-            ev_interest,
-            PollOpt::edge() | PollOpt::oneshot());
+            ev_interest);
 
-        if let Err(_) = res {
-            panic!("[unimplemented] failed to register interest with event loop");
+        if let Err(e) = res {
+            debug!("Reactor::stream_register; failed to register; err={:?}", e);
+            let action = self.conns[token].stream().fail(Error::Io(e));
+            self.handle_stream_action(action, event_loop, token);
         }
     }
-}
 
-impl Handler for IoHandler {
-    type Timeout = ();
-    type Message = Message;
+    /*
+     *
+     * ===== Udp =====
+     *
+     */
 
-    fn readable(&mut self, event_loop: &mut EventLoop<IoHandler>, token: Token, _: ReadHint) {
-        match self.conns[token] {
-            net::Evented::Listener(..) => self.accept(event_loop, token),
-            net::Evented::Stream(..) => self.read(event_loop, token),
+    fn udp(&mut self, event_loop: &mut EventLoop<IoHandler>, udp: net::Udp) {
+        let token = match self.conns.insert(net::Evented::Udp(udp)) {
+            Ok(token) => token,
+            Err(mut udp) => {
+                debug!("Reactor::udp; too many sockets, closing socket");
+                udp.udp().fail(too_many_sockets());
+                return;
+            }
+        };
+
+        let action = self.conns[token].udp().init(&self.notify, token);
+        self.handle_udp_action(action, event_loop, token);
+    }
+
+    fn udp_read(&mut self, event_loop: &mut EventLoop<IoHandler>, token: Token) {
+        let action = self.conns[token].udp().read(&self.notify, token);
+        self.handle_udp_action(action, event_loop, token);
+    }
+
+    fn udp_write(&mut self, event_loop: &mut EventLoop<IoHandler>, token: Token) {
+        let action = self.conns[token].udp().write(&self.notify, token);
+        self.handle_udp_action(action, event_loop, token);
+    }
+
+    fn udp_read_interest(&mut self,
+                         event_loop: &mut EventLoop<IoHandler>,
+                         sender: Option<Sender<(Bytes, SocketAddr)>>,
+                         token: Token) {
+
+        let action = match sender {
+            Some(sender) => self.conns[token].udp().read_interest(sender),
+            None => self.conns[token].udp().read_close(),
+        };
+
+        self.handle_udp_action(action, event_loop, token);
+    }
+
+    fn udp_write_interest(&mut self,
+                          event_loop: &mut EventLoop<IoHandler>,
+                          head: Option<((Bytes, SocketAddr), core::Stream<(Bytes, SocketAddr)>)>,
+                          token: Token) {
+
+        let action = match head {
+            Some((datagram, rest)) => self.conns[token].udp().write_interest(datagram, rest),
+            None => self.conns[token].udp().write_close(),
+        };
+
+        self.handle_udp_action(action, event_loop, token);
+    }
+
+    fn handle_udp_action(&mut self,
+                         action: Action,
+                         event_loop: &mut EventLoop<IoHandler>,
+                         token: Token) {
+
+        let action = match action {
+            // While shutting down, sockets are closed without waiting on the
+            // consumer or producer
+            Action::Wait if self.shutdown.is_some() => Action::remove(),
+            action => action,
+        };
+
+        match action {
+            Action::Register(interest) => {
+                let ev_interest = match interest {
+                    net::Interest::Read => Interest::readable(),
+                    net::Interest::Write => Interest::writable(),
+                    net::Interest::ReadWrite => Interest::readable() | Interest::writable(),
+                };
+
+                let res = register(
+                    event_loop,
+                    &mut self.conns[token],
+                    token,
+                    ev_interest);
+
+                if let Err(e) = res {
+                    debug!("Reactor::handle_udp_action; failed to register; err={:?}", e);
+                    let action = self.conns[token].udp().fail(Error::Io(e));
+                    self.handle_udp_action(action, event_loop, token);
+                }
+            }
+            Action::Remove => {
+                debug!("Closing udp socket");
+                self.conns.remove(token);
+            }
+            _ => {}
         }
     }
 
-    fn writable(&mut self, event_loop: &mut EventLoop<IoHandler>, token: Token) {
-        // Only stream type sockets are writable, so just do the write
-        self.write(event_loop, token);
+    /*
+     *
+     * ===== Signals =====
+     *
+     */
+
+    // Start receiving signals on this event loop to reap children, unless
+    // already doing so
+    fn receive_signals(&mut self, event_loop: &mut EventLoop<IoHandler>) -> Result<(), Error> {
+        if self.signals.is_some() {
+            return Ok(());
+        }
+
+        let receiver = try!(signal::Receiver::new().map_err(Error::Io));
+        let token = try!(self.register_receiver(event_loop, receiver));
+
+        self.signals = Some(token);
+        Ok(())
     }
 
-    fn notify(&mut self, event_loop: &mut EventLoop<IoHandler>, msg: Message) {
+    fn register_receiver(&mut self,
+                         event_loop: &mut EventLoop<IoHandler>,
+                         receiver: signal::Receiver) -> Result<Token, Error> {
+
+        let token = match self.conns.insert(net::Evented::Signals(receiver)) {
+            Ok(token) => token,
+            Err(_) => return Err(too_many_sockets()),
+        };
+
+        let res = register(
+            event_loop,
+            &mut self.conns[token],
+            token,
+            Interest::readable());
+
+        if let Err(e) = res {
+            self.conns.remove(token);
+            return Err(Error::Io(e));
+        }
+
+        Ok(token)
+    }
+
+    // The signal handler has written to the receiver's pipe
+    fn signaled(&mut self, event_loop: &mut EventLoop<IoHandler>, token: Token) {
+        let signals = match self.conns[token].signals().read() {
+            Ok(signals) => signals,
+            Err(e) => {
+                warn!("Reactor::signaled; failed to read signals; err={:?}", e);
+                vec![]
+            }
+        };
+
+        debug!("Reactor::signaled; signals={:?}", signals);
+
+        let id = token.as_usize();
+
+        if self.signals == Some(token) {
+            if signals.contains(&SIGCHLD) {
+                self.reap();
+            }
+        } else if let Some(subscriber) = self.subscribers.get_mut(&id) {
+            for signal in signals.into_iter().filter_map(Signal::from_raw) {
+                subscriber.deliver(signal, &self.notify, id);
+            }
+        }
+
+        let res = register(
+            event_loop,
+            &mut self.conns[token],
+            token,
+            Interest::readable());
+
+        if let Err(e) = res {
+            warn!("Reactor::signaled; failed to register receiver; err={:?}", e);
+            self.conns.remove(token);
+
+            if self.signals == Some(token) {
+                self.signals = None;
+
+                // Exits can no longer be detected
+                for watch in self.children.drain(..) {
+                    watch.fail(signals_failed());
+                }
+            } else if let Some(subscriber) = self.subscribers.remove(&id) {
+                subscriber.fail(signals_failed());
+            }
+        }
+    }
+
+    fn child(&mut self, event_loop: &mut EventLoop<IoHandler>, watch: process::Watch) {
+        if let Err(e) = self.receive_signals(event_loop) {
+            watch.fail(e);
+            return;
+        }
+
+        self.children.push(watch);
+
+        // The child may have exited before it was handed to the event loop
+        self.reap();
+    }
+
+    fn subscribe(&mut self,
+                 event_loop: &mut EventLoop<IoHandler>,
+                 subscription: signal::Subscription,
+                 tx: Sender<Signal>) {
+
+        // Each subscriber has its own receiver, which has been receiving
+        // signals since before the handlers were installed
+        let (receiver, signals, handlers) = subscription.into_parts();
+
+        let token = match self.register_receiver(event_loop, receiver) {
+            Ok(token) => token,
+            Err(e) => {
+                tx.fail(e);
+                return;
+            }
+        };
+
+        let id = token.as_usize();
+
+        let subscriber = signal::Subscriber::new(signals, handlers, tx, &self.notify, id);
+        self.subscribers.insert(id, subscriber);
+    }
+
+    // The consumer is ready for the next signal or, if `tx` is `None`, no
+    // longer interested in signals
+    fn signal_interest(&mut self, tx: Option<Sender<Signal>>, id: usize) {
+        match tx {
+            Some(tx) => {
+                match self.subscribers.get_mut(&id) {
+                    Some(subscriber) => subscriber.ready(tx, &self.notify, id),
+                    // The subscriber's receiver failed while the consumer was
+                    // busy
+                    None => tx.fail(signals_failed()),
+                }
+            }
+            // Dropping the subscriber restores the signals' previous
+            // dispositions, unless they are still used elsewhere
+            None => {
+                if self.subscribers.remove(&id).is_some() {
+                    self.conns.remove(Token(id));
+                }
+            }
+        }
+    }
+
+    // Report the exit status of the children that have exited
+    fn reap(&mut self) {
+        let mut i = 0;
+
+        while i < self.children.len() {
+            match self.children[i].try_wait() {
+                Ok(Some(status)) => {
+                    self.children.swap_remove(i).complete(status);
+                }
+                Ok(None) => {
+                    i += 1;
+                }
+                Err(e) => {
+                    self.children.swap_remove(i).fail(Error::Io(e));
+                }
+            }
+        }
+    }
+
+    /*
+     *
+     * ===== Messages =====
+     *
+     */
+
+    // Process a message taken off the event loop's queue or the overflow
+    // queue
+    fn process(&mut self, event_loop: &mut EventLoop<IoHandler>, msg: Message) {
+        self.notify.processed();
+
+        if self.shutdown.is_some() && !self.is_live(&msg) {
+            msg.fail(Error::Shutdown);
+            return;
+        }
+
         match msg {
             Message::Stream(stream) => {
                 self.stream(event_loop, stream);
             }
+            Message::Connect(connect) => {
+                self.connect(event_loop, connect);
+            }
+            Message::Timeout(tx, ms) => {
+                self.timer(event_loop, Timer::Timeout(tx), ms);
+            }
+            Message::Interval(tx, ms) => {
+                self.timer(event_loop, Timer::Interval(tx, ms), ms);
+            }
             Message::Accept(listener) => {
                 let token = match self.conns.insert(net::Evented::Listener(listener)) {
                     Ok(token) => token,
-                    Err(_) => panic!("[unimplemented] slab full - send error"),
+                    Err(mut listener) => {
+                        listener.listener().abort(too_many_sockets());
+                        return;
+                    }
                 };
 
                 self.listen(event_loop, token);
@@ -366,6 +1640,310 @@ impl Handler for IoHandler {
             Message::WriteInterest(head, token) => {
                 self.write_interest(event_loop, head, token);
             }
+            Message::WriteFailed(err, token) => {
+                let action = self.conns[token].stream().write_failed(err);
+                self.handle_stream_action(action, event_loop, token);
+            }
+            Message::Udp(udp) => {
+                self.udp(event_loop, udp);
+            }
+            Message::UdpReadInterest(tx, token) => {
+                self.udp_read_interest(event_loop, tx, token);
+            }
+            Message::UdpWriteInterest(head, token) => {
+                self.udp_write_interest(event_loop, head, token);
+            }
+            Message::UdpWriteFailed(err, token) => {
+                let action = self.conns[token].udp().write_failed(err);
+                self.handle_udp_action(action, event_loop, token);
+            }
+            Message::Child(watch) => {
+                self.child(event_loop, watch);
+            }
+            Message::Signals(subscription, tx) => {
+                self.subscribe(event_loop, subscription, tx);
+            }
+            Message::SignalInterest(tx, id) => {
+                self.signal_interest(tx, id);
+            }
+            Message::Shutdown(tx, policy) => {
+                self.shutdown(event_loop, tx, policy);
+            }
+            Message::Drain => {}
+        }
+    }
+
+    /*
+     *
+     * ===== Shutdown =====
+     *
+     */
+
+    fn shutdown(&mut self,
+                event_loop: &mut EventLoop<IoHandler>,
+                tx: Option<Complete<()>>,
+                policy: ShutdownPolicy) {
+
+        debug!("Reactor::shutdown; policy={:?}", policy);
+
+        if let Some(tx) = tx {
+            self.waiters.push(tx);
+        }
+
+        // A second request may only escalate from flushing to discarding
+        // pending data
+        match (self.shutdown, policy) {
+            (None, _) | (Some(ShutdownPolicy::Flush), ShutdownPolicy::Discard) => {}
+            _ => return,
+        }
+
+        self.shutdown = Some(policy);
+
+        for token in tokens(&self.conns) {
+            self.close(event_loop, token, policy);
+        }
+
+        for token in tokens(&self.timers) {
+            self.timers.remove(token).unwrap().fail(Error::Shutdown);
+        }
+
+        for watch in self.children.drain(..) {
+            watch.fail(Error::Shutdown);
+        }
+
+        for (_, subscriber) in self.subscribers.drain() {
+            subscriber.fail(Error::Shutdown);
+        }
+    }
+
+    // Close the socket as part of shutting down the reactor
+    fn close(&mut self, event_loop: &mut EventLoop<IoHandler>, token: Token, policy: ShutdownPolicy) {
+        match self.conns[token] {
+            net::Evented::Listener(..) => {
+                let action = self.conns[token].listener().shutdown();
+                self.handle_listener_action(action, event_loop, token);
+            }
+            net::Evented::Stream(..) => {
+                let action = self.conns[token].stream().shutdown(policy);
+                self.handle_stream_action(action, event_loop, token);
+            }
+            net::Evented::Connect(..) => {
+                let connect = self.conns.remove(token).unwrap().into_connect();
+                connect.fail(Error::Shutdown);
+            }
+            net::Evented::Udp(..) => {
+                let action = self.conns[token].udp().shutdown(policy);
+                self.handle_udp_action(action, event_loop, token);
+            }
+            net::Evented::Signals(..) => {
+                self.conns.remove(token);
+
+                if self.signals == Some(token) {
+                    self.signals = None;
+                }
+            }
+        }
+    }
+
+    // Stop the event loop once all sockets have been closed
+    fn check_shutdown(&self, event_loop: &mut EventLoop<IoHandler>) {
+        if self.shutdown.is_some() && self.conns.is_empty() {
+            event_loop.shutdown();
+        }
+    }
+
+    // While shutting down, only messages for sockets that are still being
+    // flushed are processed.
+    fn is_live(&self, msg: &Message) -> bool {
+        match *msg {
+            Message::AcceptInterest(_, token) |
+            Message::ReadInterest(_, token) |
+            Message::WriteInterest(_, token) |
+            Message::WriteFailed(_, token) |
+            Message::UdpReadInterest(_, token) |
+            Message::UdpWriteInterest(_, token) |
+            Message::UdpWriteFailed(_, token) => self.conns.contains(token),
+            Message::SignalInterest(_, id) => self.subscribers.contains_key(&id),
+            Message::Shutdown(..) => true,
+            _ => false,
         }
     }
+
+    // The event loop has exited. Close any remaining sockets, fail messages
+    // that are still queued, then notify those waiting on the shutdown.
+    fn exit(&mut self, event_loop: &mut EventLoop<IoHandler>) {
+        // Only does something if the event loop failed
+        self.shutdown(event_loop, None, ShutdownPolicy::Discard);
+        self.notify.close();
+
+        // Each iteration handles a batch of queued messages. The event loop
+        // only blocks polling for IO events when no message is pending, so
+        // stop as soon as all of them have been processed.
+        loop {
+            let depth = self.notify.depth();
+
+            if depth == 0 || event_loop.run_once(self).is_err() || self.notify.depth() == depth {
+                break;
+            }
+        }
+
+        for tx in mem::replace(&mut self.waiters, vec![]) {
+            tx.complete(());
+        }
+    }
+}
+
+// Take the parent's ends of the child's stdio pipes, in non-blocking mode
+fn pipes(child: &mut ::std::process::Child) -> io::Result<(PipeWriter, PipeReader, PipeReader)> {
+    fn pipe<T: IntoRawFd, P: FromFd + AsRawFd>(io: Option<T>) -> io::Result<P> {
+        // Owning the file descriptor first closes it on failure
+        let io = P::from_fd(io.expect("stdio is piped").into_raw_fd());
+        try!(sys::set_nonblock(&io));
+        Ok(io)
+    }
+
+    let stdin = try!(pipe(child.stdin.take()));
+    let stdout = try!(pipe(child.stdout.take()));
+    let stderr = try!(pipe(child.stderr.take()));
+
+    Ok((stdin, stdout, stderr))
+}
+
+// Returns the tokens of all the values in the slab
+fn tokens<T>(slab: &Slab<T>) -> Vec<Token> {
+    let mut ret = Vec::with_capacity(slab.count());
+    let mut i = 0;
+
+    while ret.len() < slab.count() {
+        if slab.contains(Token(i)) {
+            ret.push(Token(i));
+        }
+
+        i += 1;
+    }
+
+    ret
+}
+
+pub fn too_many_timers() -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::Other, "too many timers"))
+}
+
+fn signals_failed() -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::Other, "failed to receive signals"))
+}
+
+fn too_many_sockets() -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::Other, "too many sockets"))
+}
+
+fn queue_full() -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::Other, "reactor queue full"))
+}
+
+// Register interest in the socket with the event loop. Sockets are registered
+// as oneshot, so once registered, the socket stays known to the event loop
+// and is re-armed with `reregister` instead.
+fn register(event_loop: &mut EventLoop<IoHandler>,
+            evented: &mut net::Evented,
+            token: Token,
+            interest: Interest) -> io::Result<()> {
+
+    let (fd, registered) = evented.registration();
+
+    // Only the file descriptor is needed, which allows registering sockets
+    // behind a trait object
+    let fd = sys::Fd(fd);
+    let opts = PollOpt::edge() | PollOpt::oneshot();
+
+    if *registered {
+        return event_loop.reregister(&fd, token, interest, opts);
+    }
+
+    try!(event_loop.register_opt(&fd, token, interest, opts));
+    *registered = true;
+
+    Ok(())
+}
+
+impl Handler for IoHandler {
+    type Timeout = Timeout;
+    type Message = Message;
+
+    fn readable(&mut self, event_loop: &mut EventLoop<IoHandler>, token: Token, _: ReadHint) {
+        // The socket may have been closed by a shutdown earlier in the tick
+        if !self.conns.contains(token) {
+            return;
+        }
+
+        match self.conns[token] {
+            net::Evented::Listener(..) => self.accept(event_loop, token),
+            net::Evented::Stream(..) => self.read(event_loop, token),
+            // A connect error is reported as readable
+            net::Evented::Connect(..) => self.connected(event_loop, token),
+            net::Evented::Udp(..) => self.udp_read(event_loop, token),
+            net::Evented::Signals(..) => self.signaled(event_loop, token),
+        }
+
+        self.check_shutdown(event_loop);
+    }
+
+    fn writable(&mut self, event_loop: &mut EventLoop<IoHandler>, token: Token) {
+        // Handling the readable half of the event may have closed the socket
+        if !self.conns.contains(token) {
+            return;
+        }
+
+        match self.conns[token] {
+            net::Evented::Stream(..) => self.write(event_loop, token),
+            net::Evented::Connect(..) => self.connected(event_loop, token),
+            net::Evented::Udp(..) => self.udp_write(event_loop, token),
+            net::Evented::Listener(..) |
+            net::Evented::Signals(..) => {}
+        }
+
+        self.check_shutdown(event_loop);
+    }
+
+    fn notify(&mut self, event_loop: &mut EventLoop<IoHandler>, msg: Message) {
+        match msg {
+            Message::Drain => {}
+            msg => self.process(event_loop, msg),
+        }
+
+        // Only process the messages that are already in the overflow queue,
+        // processing a message may queue new ones.
+        for _ in 0..self.notify.overflow.len() {
+            match self.notify.overflow.pop() {
+                Some(msg) => self.process(event_loop, msg),
+                None => break,
+            }
+        }
+
+        self.check_shutdown(event_loop);
+    }
+
+    fn timeout(&mut self, event_loop: &mut EventLoop<IoHandler>, timeout: Timeout) {
+        match timeout {
+            Timeout::Accept(token) => {
+                // The listener may have been closed by a shutdown
+                if self.conns.contains(token) {
+                    self.listener_register(event_loop, token);
+                }
+            }
+            Timeout::Timer(token) => {
+                self.timer_fired(token);
+            }
+            Timeout::Stream(token, timeout) => {
+                self.stream_timeout(event_loop, token, timeout);
+            }
+        }
+
+        self.check_shutdown(event_loop);
+    }
+}
+
+// Convert the duration to milliseconds, rounding up
+pub fn millis(dur: Duration) -> u64 {
+    dur.as_secs() * 1_000 + (dur.subsec_nanos() as u64 + 999_999) / 1_000_000
 }
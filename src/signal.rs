@@ -0,0 +1,413 @@
+use core::{Async, Error, Sender};
+use libc::c_int;
+use mio::{self, PipeReader, PipeWriter};
+use nix::sys::signal::{self, SigAction, SigSet};
+use nix::sys::signal::signal::{SA_NOCLDSTOP, SA_RESTART};
+use nix::unistd;
+use reactor::Notify;
+use std::{fmt, io, mem};
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::Entry;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{Mutex, Once, ONCE_INIT};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::thread;
+use sys;
+
+// ## Implementation notes
+//
+// Signals are delivered to the event loops through self-pipes. The signal
+// handler writes the number of the signal to the write end of the pipe of
+// every receiver, while the read end is registered with the event loop like
+// any socket. The handler may only perform async-signal-safe operations,
+// so the receivers are kept in a fixed table of atomics.
+
+/// A Unix signal that can be received with `Reactor::signals`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Signal {
+    /// `SIGHUP`
+    Hup,
+    /// `SIGINT`
+    Int,
+    /// `SIGQUIT`
+    Quit,
+    /// `SIGTERM`
+    Term,
+    /// `SIGUSR1`
+    Usr1,
+    /// `SIGUSR2`
+    Usr2,
+    /// `SIGCHLD`
+    Chld,
+    /// `SIGPIPE`
+    Pipe,
+    /// `SIGALRM`
+    Alrm,
+    /// `SIGWINCH`
+    Winch,
+}
+
+impl Signal {
+    /// The signal number
+    pub fn as_raw(&self) -> c_int {
+        match *self {
+            Signal::Hup => signal::SIGHUP,
+            Signal::Int => signal::SIGINT,
+            Signal::Quit => signal::SIGQUIT,
+            Signal::Term => signal::SIGTERM,
+            Signal::Usr1 => signal::SIGUSR1,
+            Signal::Usr2 => signal::SIGUSR2,
+            Signal::Chld => signal::SIGCHLD,
+            Signal::Pipe => signal::SIGPIPE,
+            Signal::Alrm => signal::SIGALRM,
+            Signal::Winch => signal::SIGWINCH,
+        }
+    }
+
+    /// Returns the signal with the given number, if it is supported
+    pub fn from_raw(signum: c_int) -> Option<Signal> {
+        [Signal::Hup, Signal::Int, Signal::Quit, Signal::Term, Signal::Usr1,
+         Signal::Usr2, Signal::Chld, Signal::Pipe, Signal::Alrm, Signal::Winch]
+            .iter()
+            .find(|signal| signal.as_raw() == signum)
+            .cloned()
+    }
+}
+
+// Maximum number of receivers, one per signal stream and one per event loop
+// that reaps children
+const MAX_RECEIVERS: usize = 64;
+
+const UNUSED: AtomicUsize = ATOMIC_USIZE_INIT;
+
+// The write ends of the receivers' pipes, offset by one so that zero marks
+// an unused slot. Atomics are not `Copy`, so the slots are spelled out.
+static RECEIVERS: [AtomicUsize; MAX_RECEIVERS] = [
+    UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED,
+    UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED,
+    UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED,
+    UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED,
+    UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED,
+    UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED,
+    UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED,
+    UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED, UNUSED,
+];
+
+// Number of handlers currently running. A receiver waits for it to drop to
+// zero before closing its pipe, as a running handler may still write to it.
+static HANDLERS: AtomicUsize = ATOMIC_USIZE_INIT;
+
+// The handlers installed by the reactor, by signal number
+struct Installed {
+    // Number of `Handler` values for the signal
+    users: usize,
+    // The disposition the handler replaced, restored once it is unused. It is
+    // boxed as its handler is a function pointer that is null for
+    // `SIG_DFL`, which would read as `None` once wrapped in an `Option`.
+    previous: Box<SigAction>,
+}
+
+// `SigAction` holds a raw pointer that is never dereferenced, only handed
+// back to the kernel
+unsafe impl Send for Installed {}
+
+fn installed() -> &'static Mutex<HashMap<c_int, Installed>> {
+    static INIT: Once = ONCE_INIT;
+    static mut INSTALLED: *const Mutex<HashMap<c_int, Installed>> = 0 as *const _;
+
+    unsafe {
+        INIT.call_once(|| {
+            INSTALLED = Box::into_raw(Box::new(Mutex::new(HashMap::new())));
+        });
+
+        &*INSTALLED
+    }
+}
+
+// Install the handler for the given signal, replacing the current
+// disposition. The previous disposition is restored once every returned
+// `Handler` for the signal has been dropped.
+pub fn install(signum: c_int) -> io::Result<Handler> {
+    let mut installed = installed().lock().unwrap();
+
+    match installed.entry(signum) {
+        Entry::Occupied(mut entry) => {
+            entry.get_mut().users += 1;
+        }
+        Entry::Vacant(entry) => {
+            let action = SigAction::new(
+                handler,
+                SA_RESTART | SA_NOCLDSTOP,
+                SigSet::empty());
+
+            let previous = try!(unsafe { signal::sigaction(signum, &action) }
+                .map_err(sys::from_nix_error));
+
+            entry.insert(Installed {
+                users: 1,
+                previous: Box::new(previous),
+            });
+        }
+    }
+
+    Ok(Handler { signum: signum })
+}
+
+// A use of the handler installed for a signal
+pub struct Handler {
+    signum: c_int,
+}
+
+impl Drop for Handler {
+    fn drop(&mut self) {
+        let mut installed = installed().lock().unwrap();
+
+        let unused = {
+            let entry = installed.get_mut(&self.signum).expect("handler is installed");
+            entry.users -= 1;
+            entry.users == 0
+        };
+
+        if unused {
+            let entry = installed.remove(&self.signum).unwrap();
+
+            if let Err(e) = unsafe { signal::sigaction(self.signum, &entry.previous) } {
+                warn!("signal::Handler::drop; failed to restore disposition; signum={}; err={:?}",
+                      self.signum, e);
+            }
+        }
+    }
+}
+
+extern fn handler(signum: c_int) {
+    // Writing to the pipes may change errno, which the interrupted code could
+    // be about to check
+    let errno = unsafe { *sys::errno_location() };
+
+    // Counted before the slots are loaded
+    HANDLERS.fetch_add(1, Ordering::SeqCst);
+
+    let buf = [signum as u8];
+
+    for slot in RECEIVERS.iter() {
+        let fd = slot.load(Ordering::SeqCst);
+
+        if fd != 0 {
+            // If the pipe is full, the receiver has yet to read the signal
+            // numbers already in the pipe. Nothing else can be done from a
+            // signal handler.
+            let _ = unistd::write((fd - 1) as c_int, &buf);
+        }
+    }
+
+    HANDLERS.fetch_sub(1, Ordering::SeqCst);
+
+    unsafe { *sys::errno_location() = errno; }
+}
+
+// The read end of a self-pipe. The signal handler writes to the pipe for as
+// long as the receiver is alive.
+pub struct Receiver {
+    rd: PipeReader,
+    // Written to by the handler, closed once the slot has been released and
+    // no handler is running
+    _wr: PipeWriter,
+    slot: usize,
+    // Set once the pipe is known to the event loop
+    registered: bool,
+}
+
+impl Receiver {
+    pub fn new() -> io::Result<Receiver> {
+        let (rd, wr) = try!(mio::pipe());
+        let fd = wr.as_raw_fd() as usize + 1;
+
+        for (i, slot) in RECEIVERS.iter().enumerate() {
+            if slot.compare_and_swap(0, fd, Ordering::SeqCst) == 0 {
+                return Ok(Receiver {
+                    rd: rd,
+                    _wr: wr,
+                    slot: i,
+                    registered: false,
+                });
+            }
+        }
+
+        Err(io::Error::new(io::ErrorKind::Other, "too many signal receivers"))
+    }
+
+    // The pipe's file descriptor, along with whether it has been registered
+    // with the event loop yet
+    pub fn registration(&mut self) -> (RawFd, &mut bool) {
+        (self.rd.as_raw_fd(), &mut self.registered)
+    }
+
+    // Drain the pipe, returning the signals received since the last call.
+    // Each signal is returned once, however many times it was received.
+    pub fn read(&mut self) -> io::Result<Vec<c_int>> {
+        let mut signals = vec![];
+        let mut buf = [0; 64];
+
+        loop {
+            match unistd::read(self.rd.as_raw_fd(), &mut buf) {
+                Ok(0) => return Ok(signals),
+                Ok(n) => {
+                    for &signum in &buf[..n] {
+                        let signum = signum as c_int;
+
+                        if !signals.contains(&signum) {
+                            signals.push(signum);
+                        }
+                    }
+                }
+                Err(err) => {
+                    let err = sys::from_nix_error(err);
+
+                    match err.kind() {
+                        io::ErrorKind::Interrupted => {}
+                        io::ErrorKind::WouldBlock => return Ok(signals),
+                        _ => return Err(err),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        // Stop the handler from writing to the pipe before it is closed. A
+        // handler that loaded the file descriptor before the slot was
+        // cleared may still be writing to it, closing the pipe now would let
+        // it write to whatever file reuses the descriptor.
+        RECEIVERS[self.slot].store(0, Ordering::SeqCst);
+
+        while HANDLERS.load(Ordering::SeqCst) != 0 {
+            thread::yield_now();
+        }
+    }
+}
+
+// A request for signals, set up before it is handed to the event loop. The
+// receiver is created before the handlers are installed, so that signals
+// delivered in the meantime wait in its pipe.
+pub struct Subscription {
+    signals: Vec<Signal>,
+    receiver: Receiver,
+    handlers: Vec<Handler>,
+}
+
+impl Subscription {
+    pub fn new(signals: &[Signal]) -> io::Result<Subscription> {
+        let receiver = try!(Receiver::new());
+        let mut handlers = Vec::with_capacity(signals.len());
+
+        for signal in signals {
+            handlers.push(try!(install(signal.as_raw())));
+        }
+
+        Ok(Subscription {
+            signals: signals.to_vec(),
+            receiver: receiver,
+            handlers: handlers,
+        })
+    }
+
+    // The receiver is registered with the event loop, while the rest makes
+    // up the subscriber
+    pub fn into_parts(self) -> (Receiver, Vec<Signal>, Vec<Handler>) {
+        (self.receiver, self.signals, self.handlers)
+    }
+}
+
+// A consumer of signals. Like a stream's consumer, it is only sent the next
+// signal once it is ready for it. In the meantime, signals are held back,
+// with each signal held at most once, as the kernel does. The handlers stay
+// installed for as long as the subscriber is alive.
+pub struct Subscriber {
+    signals: Vec<Signal>,
+    state: State,
+    pending: VecDeque<Signal>,
+    _handlers: Vec<Handler>,
+}
+
+enum State {
+    Ready(Sender<Signal>),
+    // Waiting on the consumer
+    Waiting,
+}
+
+impl Subscriber {
+    pub fn new(signals: Vec<Signal>,
+               handlers: Vec<Handler>,
+               tx: Sender<Signal>,
+               notify: &Notify,
+               id: usize) -> Subscriber {
+
+        Subscriber::wait(tx, notify, id);
+
+        Subscriber {
+            signals: signals,
+            state: State::Waiting,
+            pending: VecDeque::new(),
+            _handlers: handlers,
+        }
+    }
+
+    // The consumer is ready for the next signal
+    pub fn ready(&mut self, tx: Sender<Signal>, notify: &Notify, id: usize) {
+        match self.pending.pop_front() {
+            Some(signal) => Subscriber::wait(tx.send(signal), notify, id),
+            None => self.state = State::Ready(tx),
+        }
+    }
+
+    // A signal has been received, deliver it if the subscriber is interested
+    pub fn deliver(&mut self, signal: Signal, notify: &Notify, id: usize) {
+        if !self.signals.contains(&signal) {
+            return;
+        }
+
+        match mem::replace(&mut self.state, State::Waiting) {
+            State::Ready(tx) => Subscriber::wait(tx.send(signal), notify, id),
+            State::Waiting => {
+                if !self.pending.iter().any(|pending| *pending == signal) {
+                    self.pending.push_back(signal);
+                }
+            }
+        }
+    }
+
+    // Signals can no longer be delivered. If the consumer is busy, it is
+    // failed once it is ready for the next signal.
+    pub fn fail(self, err: Error) {
+        if let State::Ready(tx) = self.state {
+            tx.fail(err);
+        }
+    }
+
+    fn wait<A>(tx: A, notify: &Notify, id: usize)
+            where A: Async<Value=Sender<Signal>> {
+
+        let notify = notify.clone();
+        tx.receive(move |res| {
+            match res {
+                Ok(tx) => notify.signal_interest(Some(tx), id),
+                // The consumer is no longer interested in signals
+                Err(_) => notify.signal_interest(None, id),
+            }
+        });
+    }
+}
+
+impl fmt::Debug for Receiver {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "eventual_io::signal::Receiver {{ ... }}")
+    }
+}
+
+impl fmt::Debug for Subscription {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "eventual_io::signal::Subscription {{ signals: {:?} }}", self.signals)
+    }
+}
@@ -0,0 +1,358 @@
+use bytes::MutBuf;
+use libc::{self, c_int};
+use mio;
+use nix;
+use nix::fcntl;
+use nix::sys::socket::{self, getsockopt, setsockopt, sockopt};
+use nix::sys::uio::{self, IoVec};
+use nix::unistd;
+use std::{cmp, io, mem};
+use std::net::{Shutdown, SocketAddr};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::process;
+use std::time::Duration;
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+use nix::sys::socket::sockopt::TcpKeepAlive as TcpKeepIdle;
+#[cfg(not(any(target_os = "macos", target_os = "ios")))]
+use nix::sys::socket::sockopt::TcpKeepIdle;
+
+// Returns the address of the socket's peer
+pub fn peer_addr<S: AsRawFd + ?Sized>(sock: &S) -> io::Result<SocketAddr> {
+    let addr = try!(socket::getpeername(sock.as_raw_fd()).map_err(from_nix_error));
+    inet_addr(addr)
+}
+
+// Returns the address the socket is bound to
+pub fn local_addr<S: AsRawFd + ?Sized>(sock: &S) -> io::Result<SocketAddr> {
+    let addr = try!(socket::getsockname(sock.as_raw_fd()).map_err(from_nix_error));
+    inet_addr(addr)
+}
+
+fn inet_addr(addr: socket::SockAddr) -> io::Result<SocketAddr> {
+    match addr {
+        socket::SockAddr::Inet(addr) => Ok(addr.to_std()),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "not an internet socket")),
+    }
+}
+
+// Convert a nix error to an io::Error
+pub fn from_nix_error(err: nix::Error) -> io::Error {
+    io::Error::from_raw_os_error(err.errno() as i32)
+}
+
+// Returns and clears the pending error on the socket (SO_ERROR)
+pub fn take_socket_error<S: AsRawFd + ?Sized>(sock: &S) -> io::Result<Option<io::Error>> {
+    let errno = try!(getsockopt(sock.as_raw_fd(), sockopt::SocketError)
+        .map_err(from_nix_error));
+
+    if errno == 0 {
+        Ok(None)
+    } else {
+        Ok(Some(io::Error::from_raw_os_error(errno)))
+    }
+}
+
+// Borrows a file descriptor, registering it with the event loop without
+// taking ownership
+pub struct Fd(pub RawFd);
+
+impl AsRawFd for Fd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl mio::Evented for Fd {
+}
+
+// Read from the socket into the buffer. Returns `Ok(Some(0))` once the peer
+// has closed its end and `Ok(None)` if there is nothing to read yet.
+pub fn read<S: AsRawFd + ?Sized, B: MutBuf>(sock: &S, buf: &mut B) -> io::Result<Option<usize>> {
+    loop {
+        match unistd::read(sock.as_raw_fd(), buf.mut_bytes()) {
+            Ok(n) => {
+                buf.advance(n);
+                return Ok(Some(n));
+            }
+            Err(err) => {
+                let err = from_nix_error(err);
+
+                match err.kind() {
+                    io::ErrorKind::Interrupted => {}
+                    io::ErrorKind::WouldBlock => return Ok(None),
+                    _ => return Err(err),
+                }
+            }
+        }
+    }
+}
+
+// Puts the file descriptor in non-blocking mode
+pub fn set_nonblock<S: AsRawFd + ?Sized>(sock: &S) -> io::Result<()> {
+    fcntl::fcntl(sock.as_raw_fd(), fcntl::FcntlArg::F_SETFL(fcntl::O_NONBLOCK))
+        .map(|_| ())
+        .map_err(from_nix_error)
+}
+
+// Shutdown the read half, write half or both halves of the socket
+pub fn shutdown<S: AsRawFd + ?Sized>(sock: &S, how: Shutdown) -> io::Result<()> {
+    let how = match how {
+        Shutdown::Read => socket::Shutdown::Read,
+        Shutdown::Write => socket::Shutdown::Write,
+        Shutdown::Both => socket::Shutdown::Both,
+    };
+
+    socket::shutdown(sock.as_raw_fd(), how)
+        .map_err(from_nix_error)
+}
+
+// Write the slices to the socket using a single `writev` call. Returns
+// `Ok(None)` if the socket is not ready for writing.
+pub fn writev<S: AsRawFd + ?Sized>(sock: &S, bufs: &[&[u8]]) -> io::Result<Option<usize>> {
+    let iov: Vec<IoVec<&[u8]>> = bufs.iter()
+        .map(|buf| IoVec::from_slice(buf))
+        .collect();
+
+    loop {
+        match uio::writev(sock.as_raw_fd(), &iov) {
+            Ok(n) => return Ok(Some(n)),
+            Err(err) => {
+                let err = from_nix_error(err);
+
+                match err.kind() {
+                    io::ErrorKind::Interrupted => {}
+                    io::ErrorKind::WouldBlock => return Ok(None),
+                    _ => return Err(err),
+                }
+            }
+        }
+    }
+}
+
+// Sets TCP_NODELAY, disabling Nagle's algorithm when `val` is true
+pub fn set_nodelay<S: AsRawFd + ?Sized>(sock: &S, val: bool) -> io::Result<()> {
+    setsockopt(sock.as_raw_fd(), sockopt::TcpNoDelay, &val)
+        .map_err(from_nix_error)
+}
+
+// Enables SO_KEEPALIVE, sending the first probe once the connection has been
+// idle for the given duration
+pub fn set_keepalive<S: AsRawFd + ?Sized>(sock: &S, idle: Duration) -> io::Result<()> {
+    try!(setsockopt(sock.as_raw_fd(), sockopt::KeepAlive, &true)
+        .map_err(from_nix_error));
+
+    // The kernel rejects an idle time of zero
+    let secs = cmp::max(idle.as_secs(), 1) as u32;
+
+    setsockopt(sock.as_raw_fd(), TcpKeepIdle, &secs)
+        .map_err(from_nix_error)
+}
+
+// Sets SO_LINGER. With a zero duration, closing the socket discards unsent
+// data and resets the connection. The kernel takes whole seconds, so partial
+// seconds are rounded up rather than turning into a reset.
+pub fn set_linger<S: AsRawFd + ?Sized>(sock: &S, dur: Duration) -> io::Result<()> {
+    let mut secs = dur.as_secs();
+
+    if dur.subsec_nanos() > 0 {
+        secs += 1;
+    }
+
+    let linger = socket::linger {
+        l_onoff: 1,
+        l_linger: secs as c_int,
+    };
+
+    setsockopt(sock.as_raw_fd(), sockopt::Linger, &linger)
+        .map_err(from_nix_error)
+}
+
+// Sets SO_SNDBUF
+pub fn set_send_buffer_size<S: AsRawFd + ?Sized>(sock: &S, size: usize) -> io::Result<()> {
+    set_int_option(sock, socket::SOL_SOCKET, socket::SO_SNDBUF, size as c_int)
+}
+
+// Sets SO_RCVBUF
+pub fn set_recv_buffer_size<S: AsRawFd + ?Sized>(sock: &S, size: usize) -> io::Result<()> {
+    set_int_option(sock, socket::SOL_SOCKET, socket::SO_RCVBUF, size as c_int)
+}
+
+// Sets an integer socket option that nix does not provide
+fn set_int_option<S: AsRawFd + ?Sized>(sock: &S, level: c_int, name: c_int, val: c_int) -> io::Result<()> {
+    let res = unsafe {
+        libc::setsockopt(
+            sock.as_raw_fd(),
+            level,
+            name,
+            &val as *const c_int as *const libc::c_void,
+            mem::size_of::<c_int>() as libc::socklen_t)
+    };
+
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+// Returns the pid, uid and gid of the process at the other end of a Unix
+// socket, as they were when the connection was established (SO_PEERCRED)
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn peer_cred<S: AsRawFd + ?Sized>(sock: &S) -> io::Result<(libc::pid_t, libc::uid_t, libc::gid_t)> {
+    #[repr(C)]
+    struct ucred {
+        pid: libc::pid_t,
+        uid: libc::uid_t,
+        gid: libc::gid_t,
+    }
+
+    // Not provided by libc
+    extern {
+        fn getsockopt(socket: c_int,
+                      level: c_int,
+                      name: c_int,
+                      value: *mut libc::c_void,
+                      len: *mut libc::socklen_t) -> c_int;
+    }
+
+    let mut cred = ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = mem::size_of::<ucred>() as libc::socklen_t;
+
+    let res = unsafe {
+        getsockopt(
+            sock.as_raw_fd(),
+            socket::SOL_SOCKET,
+            socket::SO_PEERCRED,
+            &mut cred as *mut ucred as *mut libc::c_void,
+            &mut len)
+    };
+
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok((cred.pid, cred.uid, cred.gid))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn peer_cred<S: AsRawFd + ?Sized>(_: &S) -> io::Result<(libc::pid_t, libc::uid_t, libc::gid_t)> {
+    Err(io::Error::new(io::ErrorKind::Other, "peer credentials are not supported on this platform"))
+}
+
+// The location of the calling thread's errno
+#[cfg(any(target_os = "linux", target_os = "dragonfly"))]
+pub unsafe fn errno_location() -> *mut c_int {
+    extern { fn __errno_location() -> *mut c_int; }
+    __errno_location()
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+pub unsafe fn errno_location() -> *mut c_int {
+    extern { fn __error() -> *mut c_int; }
+    __error()
+}
+
+#[cfg(any(target_os = "android", target_os = "netbsd", target_os = "openbsd"))]
+pub unsafe fn errno_location() -> *mut c_int {
+    extern { fn __errno() -> *mut c_int; }
+    __errno()
+}
+
+#[cfg(target_os = "solaris")]
+pub unsafe fn errno_location() -> *mut c_int {
+    extern { fn ___errno() -> *mut c_int; }
+    ___errno()
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod wait {
+    use libc::{c_int, c_uint};
+
+    pub type IdType = c_uint;
+    pub type Id = c_uint;
+
+    pub const P_PID: IdType = 1;
+    pub const WNOHANG: c_int = 0x1;
+    pub const WEXITED: c_int = 0x4;
+    pub const WNOWAIT: c_int = 0x01000000;
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+mod wait {
+    use libc::{c_int, c_uint};
+
+    pub type IdType = c_uint;
+    pub type Id = c_uint;
+
+    pub const P_PID: IdType = 1;
+    pub const WNOHANG: c_int = 0x1;
+    pub const WEXITED: c_int = 0x4;
+    pub const WNOWAIT: c_int = 0x20;
+}
+
+#[cfg(target_os = "freebsd")]
+mod wait {
+    use libc::{c_int, c_uint};
+
+    pub type IdType = c_uint;
+    pub type Id = i64;
+
+    pub const P_PID: IdType = 0;
+    pub const WNOHANG: c_int = 0x1;
+    pub const WEXITED: c_int = 0x10;
+    pub const WNOWAIT: c_int = 0x8;
+}
+
+#[cfg(target_os = "netbsd")]
+mod wait {
+    use libc::{c_int, c_uint};
+
+    pub type IdType = c_uint;
+    pub type Id = c_uint;
+
+    pub const P_PID: IdType = 1;
+    pub const WNOHANG: c_int = 0x1;
+    pub const WEXITED: c_int = 0x20;
+    pub const WNOWAIT: c_int = 0x10000;
+}
+
+#[cfg(target_os = "solaris")]
+mod wait {
+    use libc::{c_int, c_uint};
+
+    pub type IdType = c_uint;
+    pub type Id = c_int;
+
+    pub const P_PID: IdType = 0;
+    pub const WNOHANG: c_int = 0x40;
+    pub const WEXITED: c_int = 0x1;
+    pub const WNOWAIT: c_int = 0x80;
+}
+
+// Whether the child has exited. The child is left as it is, to be reaped by
+// `Child::wait`, which returns its exit status.
+pub fn has_exited(child: &process::Child) -> io::Result<bool> {
+    use self::wait::*;
+
+    extern {
+        fn waitid(idtype: IdType, id: Id, infop: *mut libc::c_void, options: c_int) -> c_int;
+    }
+
+    // Large enough for any platform's `siginfo_t`, which starts with the
+    // signal number. It stays zero unless the child has exited.
+    let mut info = [0u64; 32];
+
+    let res = unsafe {
+        waitid(P_PID,
+               child.id() as Id,
+               info.as_mut_ptr() as *mut libc::c_void,
+               WEXITED | WNOHANG | WNOWAIT)
+    };
+
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { *(info.as_ptr() as *const c_int) } != 0)
+}
@@ -1,6 +1,6 @@
 use bytes::{Bytes, ToBytes};
-use eventual::Future;
-use eio;
+use eventual::{Async, AsyncError, Future};
+use eio::{self, Error};
 use eio::frame::{Frame, Len};
 
 #[test]
@@ -41,6 +41,31 @@ pub fn test_one_frame() {
     assert_eq!(chunks[1], b"barbaz".to_bytes());
 }
 
+#[test]
+pub fn test_framing_truncated_frame() {
+    let s = stream(vec![b"foo", b"ba"])
+        .frame(Len::new(3));
+
+    let chunks: Vec<Bytes> = s.iter().collect();
+
+    // The data left over at the end of the source is forwarded as is
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0], b"foo".to_bytes());
+    assert_eq!(chunks[1], b"ba".to_bytes());
+}
+
+#[test]
+pub fn test_one_frame_truncated() {
+    let s = stream(vec![b"fo"])
+        .frame_one(Len::new(3));
+
+    match s.await() {
+        Err(AsyncError::Failed(Error::Frame(..))) => {}
+        Err(e) => panic!("unexpected error; {:?}", e),
+        Ok(_) => panic!("expected the frame to fail"),
+    }
+}
+
 fn stream(mut chunks: Vec<&'static [u8]>) -> eio::Stream<Bytes> {
     Future::lazy(move || {
         if chunks.is_empty() {
@@ -1,14 +1,36 @@
+extern crate libc;
 extern crate mio;
+extern crate nix;
 extern crate bytes;
 extern crate eventual;
 extern crate eventual_io as eio;
 extern crate env_logger;
+extern crate time;
 
 #[macro_use]
 extern crate log;
 
 mod frame;
+mod test_executor;
+mod test_pipe;
+mod test_process;
+mod test_reactor_builder;
+mod test_reactor_notify;
+mod test_reactor_pool;
+mod test_reactor_shutdown;
+mod test_signal;
+mod test_tcp_accept;
+mod test_tcp_addr;
+mod test_tcp_buffer;
+mod test_tcp_connect;
 mod test_tcp_echo;
+mod test_tcp_half_close;
+mod test_tcp_options;
+mod test_tcp_timeout;
+mod test_tcp_write;
+mod test_timer;
+mod test_udp;
+mod test_unix;
 
 mod addr {
     use std::net::SocketAddr;
@@ -36,3 +58,77 @@ mod addr {
         FromStr::from_str(&s).unwrap()
     }
 }
+
+mod server {
+    use addr;
+    use bytes::{Buf, ByteStr, Bytes};
+    use mio::{tcp, NonBlock, Socket};
+    use mio::tcp::TcpListener;
+    use eio::{Connection, Reactor, Stream, StreamOptions};
+    use eventual::Async;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    // Open a listening socket on the given address
+    pub fn listen(addr: &SocketAddr) -> NonBlock<TcpListener> {
+        let srv = tcp::v4().unwrap();
+        srv.set_reuseaddr(true).unwrap();
+        srv.bind(addr).unwrap();
+        srv.listen(256).unwrap()
+    }
+
+    // Echo everything back on each connection accepted on the address
+    pub fn echo(reactor: &Reactor, addr: &SocketAddr, options: StreamOptions) {
+        reactor.accept_with(listen(addr), options)
+            .each(|conn| {
+                let (tx, rx) = conn.into_pair();
+                tx.send_all(rx).fire()
+            })
+            .fire();
+    }
+
+    // Wait for the first connection on the accept stream
+    pub fn accept(conns: Stream<Connection>) -> Connection {
+        match conns.await() {
+            Ok(Some((conn, _))) => conn,
+            _ => panic!("expected a connection"),
+        }
+    }
+
+    // Join the chunks into a single buffer
+    pub fn concat(chunks: &[Bytes]) -> Vec<u8> {
+        chunks.iter().fold(vec![], |mut ret, chunk| {
+            let mut bytes = vec![0; chunk.len()];
+            chunk.buf().read_slice(&mut bytes);
+            ret.extend(bytes);
+            ret
+        })
+    }
+
+    // Send the chunks from a client to a server stream, each side using the
+    // given options, and return the chunks the server received. The server
+    // only starts reading once the data has had time to arrive, so that
+    // reads fill the buffers.
+    pub fn transfer(server: StreamOptions, client: StreamOptions, chunks: Vec<Bytes>) -> Vec<Bytes> {
+        let addr = addr::localhost();
+
+        let reactor = Reactor::start().unwrap();
+
+        let conns = reactor.accept_with(listen(&addr), server);
+
+        let (mut tx, _) = reactor.connect_with(&addr, client).await().unwrap().into_pair();
+
+        let (_, srv_rx) = accept(conns).into_pair();
+
+        // Send the chunks one at a time, then end the write stream
+        for chunk in chunks {
+            tx = tx.send(chunk).await().unwrap();
+        }
+
+        drop(tx);
+
+        reactor.timeout(Duration::from_millis(50)).await().unwrap();
+
+        srv_rx.iter().collect()
+    }
+}
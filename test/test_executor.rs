@@ -0,0 +1,147 @@
+use {addr, server};
+use bytes::{ByteStr, ToBytes};
+use eio::{Error, Execute, Executor, Reactor, StreamOptions, Task};
+use eio::frame::{self, Frame};
+use eventual::{Async, AsyncError};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::{mpsc, Arc};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+// Echo a message through a server using the given executor
+fn echo(executor: Executor) {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let options = StreamOptions::new().executor(executor);
+
+    server::echo(&reactor, &addr, options.clone());
+
+    let msg = b"hello".to_bytes();
+
+    let (tx, rx) = reactor.connect_with(&addr, options).await().unwrap().into_pair();
+    drop(tx.send(msg.clone()).await().unwrap());
+
+    let (echo, _) = rx.frame_one(frame::Len::new(msg.len()))
+        .await().unwrap()
+        .expect("unexpected EOF");
+
+    assert_eq!(echo, msg);
+}
+
+#[test]
+pub fn test_thread_pool_executor() {
+    echo(Executor::thread_pool(2));
+}
+
+#[test]
+pub fn test_custom_executor() {
+    // Runs each task on a new thread, counting the tasks
+    struct Spawn(Arc<AtomicUsize>);
+
+    impl Execute for Spawn {
+        fn execute(&self, task: Task) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            thread::spawn(move || task.run());
+        }
+    }
+
+    let tasks = Arc::new(AtomicUsize::new(0));
+
+    echo(Executor::new(Spawn(tasks.clone())));
+
+    assert!(tasks.load(Ordering::SeqCst) > 0);
+}
+
+#[test]
+pub fn test_blocked_callback_does_not_stall_event_loop() {
+    let slow = addr::localhost();
+    let fast = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let options = StreamOptions::new().executor(Executor::thread_pool(2));
+
+    // The consumer of the slow connection blocks until released
+    let (started_tx, started_rx) = mpsc::channel();
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+    let mut blocked = Some((started_tx, release_rx));
+
+    reactor.accept_with(server::listen(&slow), options.clone())
+        .each(move |conn| {
+            let (started, release) = blocked.take().unwrap();
+            let (_, rx) = conn.into_pair();
+
+            rx.receive(move |_| {
+                started.send(()).unwrap();
+                release.recv().unwrap();
+            });
+        })
+        .fire();
+
+    server::echo(&reactor, &fast, options);
+
+    let mut sock = TcpStream::connect(&slow).unwrap();
+    sock.write_all(b"hello").unwrap();
+
+    started_rx.recv().unwrap();
+
+    // While the callback is blocked, the event loop keeps serving the other
+    // connection
+    let mut sock = TcpStream::connect(&fast).unwrap();
+    sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    let mut buf = [0; 4];
+    sock.write_all(b"ping").unwrap();
+    sock.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+
+    release_tx.send(()).unwrap();
+
+    reactor.shutdown().await().unwrap();
+}
+
+// Fail the producer of a connection using the given executor, the consumer is
+// failed with the producer's error
+fn producer_failed(executor: Executor) {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    server::echo(&reactor, &addr, StreamOptions::new());
+
+    let options = StreamOptions::new().executor(executor);
+
+    let (tx, mut rx) = reactor.connect_with(&addr, options).await().unwrap().into_pair();
+    let tx = tx.send(b"hello".to_bytes()).await().unwrap();
+
+    tx.fail(Error::Frame("producer failed"));
+
+    // The echoed data may arrive before the failure
+    loop {
+        match rx.await() {
+            Ok(Some((_, rest))) => rx = rest,
+            Err(AsyncError::Failed(Error::Frame(msg))) => {
+                assert_eq!(msg, "producer failed");
+                break;
+            }
+            Err(e) => panic!("unexpected error; {:?}", e),
+            Ok(None) => panic!("expected the stream to fail"),
+        }
+    }
+
+    reactor.shutdown().await().unwrap();
+}
+
+#[test]
+pub fn test_producer_failed_inline() {
+    producer_failed(Executor::inline());
+}
+
+#[test]
+pub fn test_producer_failed_thread_pool() {
+    producer_failed(Executor::thread_pool(2));
+}
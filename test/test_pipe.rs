@@ -0,0 +1,107 @@
+use bytes::ToBytes;
+use mio;
+use nix::fcntl::{fcntl, O_NONBLOCK};
+use nix::fcntl::FcntlArg::F_SETFL;
+use nix::sys::socket::{self, AddressFamily, SockFlag, SockType};
+use nix::unistd;
+use eio::{self, Reactor};
+use eio::frame::{self, Frame};
+use eventual::Async;
+use std::io;
+use std::net::Shutdown;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[test]
+pub fn test_pipe_transfer() {
+    let reactor = Reactor::start().unwrap();
+
+    let (rd, wr) = mio::pipe().unwrap();
+
+    // Each end only supports one direction
+    let (tx, _) = reactor.register(wr);
+    let (_, rx) = reactor.register(rd);
+
+    let tx = tx.send(b"hello".to_bytes()).await().unwrap();
+
+    let (chunk, rest) = rx.frame_one(frame::Len::new(5)).await().unwrap().unwrap();
+    assert_eq!(chunk, b"hello".to_bytes());
+
+    // Closing the write end is seen as EOF by the reader
+    drop(tx);
+    assert!(rest.await().unwrap().is_none());
+
+    reactor.shutdown().await().unwrap();
+}
+
+// A file descriptor type defined outside of the crate, owning one end of a
+// socket pair
+struct Channel {
+    fd: RawFd,
+    closed: Arc<AtomicBool>,
+}
+
+impl AsRawFd for Channel {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl mio::Evented for Channel {
+}
+
+impl eio::Io for Channel {
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        let how = match how {
+            Shutdown::Read => socket::Shutdown::Read,
+            Shutdown::Write => socket::Shutdown::Write,
+            Shutdown::Both => socket::Shutdown::Both,
+        };
+
+        socket::shutdown(self.fd, how)
+            .map_err(|e| io::Error::from_raw_os_error(e.errno() as i32))
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        let _ = unistd::close(self.fd);
+        self.closed.store(true, Ordering::SeqCst);
+    }
+}
+
+#[test]
+pub fn test_custom_io() {
+    let reactor = Reactor::start().unwrap();
+
+    let (fd, peer) = socket::socketpair(AddressFamily::Unix, SockType::Stream, 0, SockFlag::empty()).unwrap();
+    fcntl(fd, F_SETFL(O_NONBLOCK)).unwrap();
+
+    let closed = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = reactor.register(Channel { fd: fd, closed: closed.clone() });
+
+    // Reading
+    socket::send(peer, b"hello", 0).unwrap();
+
+    let (chunk, rx) = rx.frame_one(frame::Len::new(5)).await().unwrap().unwrap();
+    assert_eq!(chunk, b"hello".to_bytes());
+
+    // Writing
+    let tx = tx.send(b"world".to_bytes()).await().unwrap();
+
+    let mut buf = [0; 64];
+    let n = socket::recv(peer, &mut buf, 0).unwrap();
+    assert_eq!(&buf[..n], b"world");
+
+    // Ending the write stream shuts down the write half through the trait
+    drop(tx);
+    assert_eq!(socket::recv(peer, &mut buf, 0).unwrap(), 0);
+
+    // Once the peer closes its end, the stream is done and drops the value
+    unistd::close(peer).unwrap();
+    assert!(rx.await().unwrap().is_none());
+
+    reactor.shutdown().await().unwrap();
+    assert!(closed.load(Ordering::SeqCst));
+}
@@ -0,0 +1,43 @@
+use bytes::{Bytes, ByteStr, ToBytes};
+use eio::Reactor;
+use eventual::Async;
+use std::process::Command;
+
+fn concat(chunks: Vec<Bytes>) -> Bytes {
+    chunks.into_iter().fold(Bytes::empty(), |acc, chunk| acc.concat(&chunk))
+}
+
+#[test]
+pub fn test_process_stdio() {
+    let reactor = Reactor::start().unwrap();
+
+    let child = reactor.spawn(Command::new("cat")).unwrap();
+    let (stdin, stdout, stderr, status) = child.into_parts();
+
+    // Closing stdin lets `cat` exit
+    drop(stdin.send(b"hello".to_bytes()).await().unwrap());
+
+    let out = stdout.collect().await().unwrap();
+    assert_eq!(concat(out), b"hello".to_bytes());
+
+    assert!(stderr.collect().await().unwrap().is_empty());
+    assert!(status.await().unwrap().success());
+
+    reactor.shutdown().await().unwrap();
+}
+
+#[test]
+pub fn test_process_exit_status() {
+    let reactor = Reactor::start().unwrap();
+
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg("echo oops >&2; exit 3");
+
+    let (_, stdout, stderr, status) = reactor.spawn(cmd).unwrap().into_parts();
+
+    assert!(stdout.collect().await().unwrap().is_empty());
+    assert_eq!(concat(stderr.collect().await().unwrap()), b"oops\n".to_bytes());
+    assert_eq!(status.await().unwrap().code(), Some(3));
+
+    reactor.shutdown().await().unwrap();
+}
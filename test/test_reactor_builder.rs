@@ -0,0 +1,89 @@
+use {addr, server};
+use mio::tcp;
+use eio::{Error, ReactorBuilder};
+use eventual::{Async, AsyncError};
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+pub fn test_builder_configures_event_loops() {
+    let (tx, rx) = mpsc::channel();
+    let tx = Mutex::new(tx);
+
+    let reactor = ReactorBuilder::new()
+        .threads(2)
+        .max_sockets(16)
+        .notify_capacity(64)
+        .timer_tick(Duration::from_millis(10))
+        .timer_wheel_size(64)
+        .messages_per_tick(8)
+        .thread_name("reactor")
+        .affinity(move |id| {
+            let name = thread::current().name().map(|name| name.to_string());
+            tx.lock().unwrap().send((id, name)).unwrap();
+        })
+        .start().unwrap();
+
+    // The hook runs once on each event loop thread
+    let mut started: Vec<_> = rx.iter().take(2).collect();
+    started.sort();
+
+    assert_eq!(started, vec![
+        (0, Some("reactor-0".to_string())),
+        (1, Some("reactor-1".to_string())),
+    ]);
+
+    reactor.timeout(Duration::from_millis(20))
+        .await().unwrap();
+
+    reactor.shutdown().await().unwrap();
+}
+
+#[test]
+pub fn test_builder_clamps_zero_sizes() {
+    let reactor = ReactorBuilder::new()
+        .threads(0)
+        .max_sockets(0)
+        .notify_capacity(0)
+        .timer_tick(Duration::from_millis(0))
+        .timer_wheel_size(0)
+        .timer_capacity(0)
+        .messages_per_tick(0)
+        .start().unwrap();
+
+    reactor.timeout(Duration::from_millis(20))
+        .await().unwrap();
+
+    reactor.shutdown().await().unwrap();
+}
+
+#[test]
+pub fn test_full_slab_fails_new_sockets() {
+    let addr = addr::localhost();
+
+    let reactor = ReactorBuilder::new()
+        .max_sockets(1)
+        .start().unwrap();
+
+    // The listener takes up the only slot
+    let _socks = reactor.accept(server::listen(&addr));
+
+    match reactor.connect(&addr).await() {
+        Err(AsyncError::Failed(Error::Io(..))) => {}
+        Err(e) => panic!("unexpected error; {:?}", e),
+        Ok(_) => panic!("expected the connect to fail"),
+    }
+
+    let (io, _) = tcp::connect(&addr).unwrap();
+    let (_, rx) = reactor.stream(io).into_pair();
+
+    match rx.await() {
+        Err(AsyncError::Failed(Error::Io(..))) => {}
+        Err(e) => panic!("unexpected error; {:?}", e),
+        Ok(_) => panic!("expected the stream to fail"),
+    }
+
+    reactor.shutdown().await().unwrap();
+}
@@ -0,0 +1,61 @@
+use {addr, server};
+use bytes::{ByteStr, ToBytes};
+use eio::{ReactorBuilder, StreamOptions};
+use eio::frame::{self, Frame};
+use eventual::{self, Async};
+use std::sync::mpsc;
+use std::time::Duration;
+
+#[test]
+pub fn test_full_notify_queue_overflows() {
+    let addr = addr::localhost();
+
+    // A tiny queue, so that most messages go through the overflow queue
+    let reactor = ReactorBuilder::new()
+        .notify_capacity(2)
+        .start().unwrap();
+
+    server::echo(&reactor, &addr, StreamOptions::new());
+
+    let mut pairs = vec![];
+
+    for _ in 0..16 {
+        pairs.push(reactor.connect(&addr).await().unwrap().into_pair());
+    }
+
+    // Block the event loop, so that the messages pile up
+    let (blocked_tx, blocked_rx) = mpsc::channel();
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+
+    reactor.timeout(Duration::from_millis(50))
+        .receive(move |_| {
+            blocked_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+        });
+
+    blocked_rx.recv().unwrap();
+
+    // Talk on all connections at once
+    let echoes: Vec<_> = pairs.into_iter().enumerate()
+        .map(|(i, (tx, rx))| {
+            let msg = format!("hello {}", i).as_bytes().to_bytes();
+
+            tx.send(msg.clone()).fire();
+
+            rx.frame_one(frame::Len::new(msg.len()))
+                .collect()
+                .map(move |echo| assert_eq!(echo, vec![msg]))
+        })
+        .collect();
+
+    // Each write queued a message, most of them did not fit in the queue
+    assert!(reactor.queue_depth() >= 16);
+    assert!(reactor.overflow_high_water() >= 14);
+
+    release_tx.send(()).unwrap();
+
+    eventual::join(echoes).await().unwrap();
+
+    reactor.shutdown().await().unwrap();
+    assert_eq!(reactor.queue_depth(), 0);
+}
@@ -0,0 +1,76 @@
+use {addr, server};
+use bytes::{ByteStr, ToBytes};
+use eio::{Reactor, ReactorBuilder, StreamOptions};
+use eio::frame::{self, Frame};
+use eventual::Async;
+use std::collections::HashSet;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::thread;
+
+#[test]
+pub fn test_pool_echo() {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::pool(4).unwrap();
+
+    // Echo everything back on each accepted connection
+    server::echo(&reactor, &addr, StreamOptions::new());
+
+    for i in 0..8 {
+        let msg = format!("hello {}", i).as_bytes().to_bytes();
+
+        let (tx, rx) = reactor.connect(&addr).await().unwrap().into_pair();
+        drop(tx.send(msg.clone()).await().unwrap());
+
+        let (echo, _) = rx.frame_one(frame::Len::new(msg.len()))
+            .await().unwrap()
+            .expect("unexpected EOF");
+
+        assert_eq!(echo, msg);
+    }
+
+    reactor.shutdown().await().unwrap();
+}
+
+#[test]
+pub fn test_pool_spreads_connections() {
+    let addr = addr::localhost();
+
+    let reactor = ReactorBuilder::new()
+        .threads(4)
+        .thread_name("pool")
+        .start().unwrap();
+
+    let (tx, rx) = mpsc::channel();
+
+    // Data read from a connection is delivered on the thread of the event
+    // loop that the connection was handed to
+    reactor.accept(server::listen(&addr))
+        .each(move |conn| {
+            let tx = tx.clone();
+            let (_, rx) = conn.into_pair();
+
+            rx.receive(move |_| {
+                tx.send(thread::current().name().unwrap().to_string()).unwrap();
+            });
+        })
+        .fire();
+
+    // Plain sockets for clients, so that only accepted connections are
+    // spread across the event loops
+    let clients: Vec<_> = (0..8).map(|_| {
+        let mut sock = TcpStream::connect(&addr).unwrap();
+        sock.write_all(b"hello").unwrap();
+        sock
+    }).collect();
+
+    let threads: HashSet<_> = (0..8).map(|_| rx.recv().unwrap()).collect();
+
+    assert_eq!(threads.len(), 4);
+
+    drop(clients);
+
+    reactor.shutdown().await().unwrap();
+}
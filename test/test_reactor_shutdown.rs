@@ -0,0 +1,120 @@
+use {addr, server};
+use bytes::ToBytes;
+use eio::{Error, Reactor, ShutdownPolicy, StreamOptions};
+use eventual::{Async, AsyncError};
+use std::io::Read;
+use std::net::TcpStream;
+use std::time::Duration;
+use time;
+
+#[test]
+pub fn test_shutdown_fails_pending_timers() {
+    let reactor = Reactor::start().unwrap();
+    let timeout = reactor.timeout(Duration::from_millis(10_000));
+
+    reactor.shutdown().await().unwrap();
+
+    match timeout.await() {
+        Err(AsyncError::Failed(Error::Shutdown)) => {}
+        res => panic!("unexpected result; {:?}", res),
+    }
+}
+
+#[test]
+pub fn test_shutdown_closes_listeners() {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let socks = reactor.accept(server::listen(&addr));
+
+    reactor.shutdown().await().unwrap();
+
+    match socks.await() {
+        Err(AsyncError::Failed(Error::Shutdown)) => {}
+        Err(e) => panic!("unexpected error; {:?}", e),
+        Ok(_) => panic!("expected the accept stream to fail"),
+    }
+
+    // The reactor is gone, new work fails right away
+    match reactor.timeout(Duration::from_millis(10)).await() {
+        Err(AsyncError::Failed(Error::Shutdown)) => {}
+        res => panic!("unexpected result; {:?}", res),
+    }
+}
+
+#[test]
+pub fn test_shutdown_completes_promptly() {
+    let reactor = Reactor::start().unwrap();
+    let start = time::precise_time_ns();
+
+    reactor.shutdown().await().unwrap();
+
+    // The event loop does not sit out a full IO poll timeout (1s) on exit
+    assert!(time::precise_time_ns() - start < 500_000_000);
+}
+
+#[test]
+pub fn test_shutdown_on_drop() {
+    let reactor = Reactor::start().unwrap();
+    let timeout = reactor.timeout(Duration::from_millis(10_000));
+
+    // The reactor keeps running as long as a handle is left
+    let other = reactor.clone();
+    drop(reactor);
+
+    other.timeout(Duration::from_millis(10)).await().unwrap();
+
+    // Dropping the last handle shuts it down
+    drop(other);
+
+    match timeout.await() {
+        Err(AsyncError::Failed(Error::Shutdown)) => {}
+        res => panic!("unexpected result; {:?}", res),
+    }
+}
+
+#[test]
+pub fn test_shutdown_flushes_pending_writes() {
+    let (data, received) = shutdown_with_pending_writes(ShutdownPolicy::Flush);
+    assert_eq!(received, data.len());
+}
+
+#[test]
+pub fn test_shutdown_discards_pending_writes() {
+    let (data, received) = shutdown_with_pending_writes(ShutdownPolicy::Discard);
+    assert!(received < data.len());
+}
+
+// Shut the reactor down while a stream has more data pending than fits in the
+// socket buffers. Returns the data and the number of bytes the peer received.
+fn shutdown_with_pending_writes(policy: ShutdownPolicy) -> (Vec<u8>, usize) {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let options = StreamOptions::new()
+        .send_buffer_size(64 * 1024);
+
+    let conns = reactor.accept_with(server::listen(&addr), options);
+
+    // Not read from until the reactor is shutting down
+    let mut client = TcpStream::connect(&addr).unwrap();
+
+    let (tx, _) = server::accept(conns).into_pair();
+
+    // The producer is kept around, so the data is only flushed because of the
+    // shutdown policy
+    let data = vec![0u8; 16 * 1024 * 1024];
+    let tx = tx.send((&data).to_bytes()).await().unwrap();
+
+    let done = reactor.shutdown_with(policy);
+
+    let mut received = vec![];
+    let _ = client.read_to_end(&mut received);
+
+    done.await().unwrap();
+    drop(tx);
+
+    (data, received.len())
+}
@@ -0,0 +1,77 @@
+use eio::{Reactor, Signal};
+use eventual::Async;
+use libc::c_int;
+use nix::sys::signal::{self, SigAction, SigSet, SockFlag};
+use nix::unistd;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use std::time::Duration;
+
+#[test]
+pub fn test_signal_stream() {
+    let reactor = Reactor::start().unwrap();
+
+    let signals = reactor.signals(&[Signal::Usr1]);
+
+    // Have a child send the signal to this process
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg("kill -USR1 $PPID");
+
+    let (_, _, _, status) = reactor.spawn(cmd).unwrap().into_parts();
+    assert!(status.await().unwrap().success());
+
+    match signals.await() {
+        Ok(Some((signal, _))) => assert_eq!(signal, Signal::Usr1),
+        _ => panic!("expected a signal"),
+    }
+
+    reactor.shutdown().await().unwrap();
+}
+
+static RAISED: AtomicBool = ATOMIC_BOOL_INIT;
+
+extern fn raised(_: c_int) {
+    RAISED.store(true, Ordering::SeqCst);
+}
+
+#[test]
+pub fn test_signal_stream_dropped() {
+    let reactor = Reactor::start().unwrap();
+
+    // The disposition in place before the reactor's handler
+    let action = SigAction::new(raised, SockFlag::empty(), SigSet::empty());
+    unsafe { signal::sigaction(signal::SIGUSR2, &action).unwrap(); }
+
+    let signals = reactor.signals(&[Signal::Usr2]);
+
+    // Sent right away, before the event loop has seen the stream
+    signal::kill(unistd::getpid(), signal::SIGUSR2).unwrap();
+
+    let signals = match signals.await() {
+        Ok(Some((signal, rest))) => {
+            assert_eq!(signal, Signal::Usr2);
+            rest
+        }
+        _ => panic!("expected a signal"),
+    };
+
+    assert!(!RAISED.load(Ordering::SeqCst));
+
+    // Once the event loop sees that the stream is gone, the signal goes to
+    // the previous disposition again
+    drop(signals);
+
+    for _ in 0..100 {
+        signal::kill(unistd::getpid(), signal::SIGUSR2).unwrap();
+
+        if RAISED.load(Ordering::SeqCst) {
+            break;
+        }
+
+        reactor.timeout(Duration::from_millis(10)).await().unwrap();
+    }
+
+    assert!(RAISED.load(Ordering::SeqCst));
+
+    reactor.shutdown().await().unwrap();
+}
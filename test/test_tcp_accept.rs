@@ -0,0 +1,139 @@
+use {addr, server};
+use libc;
+use mio::{tcp, FromFd, NonBlock};
+use mio::tcp::TcpListener;
+use nix::sys::socket::{self, Shutdown};
+use nix::unistd;
+use eio::{Error, Reactor};
+use eventual::{Async, AsyncError};
+use std::env;
+use std::fs::File;
+use std::net::SocketAddr;
+use std::os::unix::io::AsRawFd;
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::time::Duration;
+
+#[test]
+pub fn test_tcp_accept_spurious_wakeup() {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    // Two listeners on the same socket. A new connection wakes up both, but
+    // only one of them gets to accept it.
+    let srv = server::listen(&addr);
+    let dup: NonBlock<TcpListener> = NonBlock::new(FromFd::from_fd(unistd::dup(srv.as_raw_fd()).unwrap()));
+
+    let (tx, rx) = mpsc::channel();
+
+    for (name, conns) in vec![("a", reactor.accept(srv)), ("b", reactor.accept(dup))] {
+        let tx = tx.clone();
+
+        conns.receive(move |res| {
+            tx.send((name, res.ok().and_then(|head| head))).unwrap();
+        });
+    }
+
+    // Give both listeners a chance to register interest
+    reactor.timeout(Duration::from_millis(50)).await().unwrap();
+
+    let (c1, _) = tcp::connect(&addr).unwrap();
+    let (first, head) = rx.recv().unwrap();
+    assert!(head.is_some());
+
+    // The listener that accepted is left waiting on its consumer, so the next
+    // connection can only be accepted by the other one, which must have
+    // survived the wakeup without a pending socket
+    let (c2, _) = tcp::connect(&addr).unwrap();
+    let (second, head) = rx.recv().unwrap();
+    assert!(head.is_some());
+    assert!(first != second);
+
+    drop((c1, c2));
+
+    reactor.shutdown().await().unwrap();
+}
+
+// Running out of file descriptors affects the whole process, so the test runs
+// in a child process of its own
+#[test]
+pub fn test_tcp_accept_out_of_fds() {
+    if let Ok(addr) = env::var("EIO_TEST_OUT_OF_FDS") {
+        return accept_out_of_fds(FromStr::from_str(&addr).unwrap());
+    }
+
+    let status = Command::new(env::current_exe().unwrap())
+        .arg("test_tcp_accept_out_of_fds")
+        .env("EIO_TEST_OUT_OF_FDS", addr::localhost().to_string())
+        .status().unwrap();
+
+    assert!(status.success());
+}
+
+fn accept_out_of_fds(addr: SocketAddr) {
+    let reactor = Reactor::start().unwrap();
+    let conns = reactor.accept(server::listen(&addr));
+
+    // The connection is pending in the backlog before descriptors run out
+    let (client, _) = tcp::connect(&addr).unwrap();
+
+    unsafe {
+        let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        assert_eq!(libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit), 0);
+
+        limit.rlim_cur = 256;
+        assert_eq!(libc::setrlimit(libc::RLIMIT_NOFILE, &limit), 0);
+    }
+
+    let mut files = vec![];
+
+    while let Ok(file) = File::open("/dev/null") {
+        files.push(file);
+    }
+
+    let (tx, rx) = mpsc::channel();
+
+    conns.receive(move |res| {
+        tx.send(res.ok().and_then(|head| head)).unwrap();
+    });
+
+    // Accepting fails while no descriptors are left, the listener backs off
+    // and keeps retrying
+    reactor.timeout(Duration::from_millis(300)).await().unwrap();
+    assert!(rx.try_recv().is_err());
+
+    drop(files);
+
+    // The connection is accepted once descriptors are available again
+    assert!(rx.recv().unwrap().is_some());
+
+    drop(client);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+pub fn test_tcp_accept_fatal_error() {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let srv = server::listen(&addr);
+    let fd = srv.as_raw_fd();
+
+    let conns = reactor.accept(srv);
+
+    // Accepting from a socket that was shut down fails with EINVAL on Linux
+    socket::shutdown(fd, Shutdown::Both).unwrap();
+
+    match conns.await() {
+        Err(AsyncError::Failed(Error::Io(e))) => {
+            assert_eq!(e.raw_os_error(), Some(libc::EINVAL));
+        }
+        Err(e) => panic!("unexpected error; {:?}", e),
+        Ok(_) => panic!("expected the accept stream to fail"),
+    }
+
+    reactor.shutdown().await().unwrap();
+}
@@ -0,0 +1,24 @@
+use {addr, server};
+use mio::tcp;
+use eio::Reactor;
+use eventual::Async;
+
+#[test]
+pub fn test_tcp_accept_addrs() {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let conns = reactor.accept(server::listen(&addr));
+
+    let (sock, _) = tcp::connect(&addr).unwrap();
+    let client = reactor.stream(sock);
+
+    let conn = server::accept(conns);
+
+    assert_eq!(conn.local_addr(), Some(addr));
+    assert!(client.local_addr().is_some());
+    assert_eq!(conn.peer_addr(), client.local_addr());
+
+    reactor.shutdown().await().unwrap();
+}
@@ -0,0 +1,100 @@
+use server;
+use bytes::{ByteStr, ToBytes};
+use eio::{BufferPool, BufferStrategy, StreamOptions};
+use std::usize;
+
+fn data() -> Vec<u8> {
+    (0..256 * 1024).map(|i| i as u8).collect()
+}
+
+#[test]
+pub fn test_tcp_fixed_buffer() {
+    let data = data();
+    let options = StreamOptions::new()
+        .buffer_strategy(BufferStrategy::Fixed(1024));
+
+    let chunks = server::transfer(options, StreamOptions::new(), vec![(&data).to_bytes()]);
+
+    assert_eq!(server::concat(&chunks), data);
+    assert!(chunks.iter().all(|chunk| chunk.len() <= 1024));
+}
+
+#[test]
+pub fn test_tcp_adaptive_buffer() {
+    let data = data();
+    let options = StreamOptions::new()
+        .buffer_strategy(BufferStrategy::Adaptive { min: 1024, max: 64 * 1024 });
+
+    let chunks = server::transfer(options, StreamOptions::new(), vec![(&data).to_bytes()]);
+
+    assert_eq!(server::concat(&chunks), data);
+
+    // The first read fills the minimum sized buffer, the following ones grow
+    // up to the maximum
+    assert!(chunks[0].len() <= 1024);
+    assert!(chunks.iter().any(|chunk| chunk.len() > 1024));
+    assert!(chunks.iter().all(|chunk| chunk.len() <= 64 * 1024));
+}
+
+#[test]
+pub fn test_tcp_pooled_buffer() {
+    let data = data();
+    let pool = BufferPool::new(16 * 1024, 8);
+    let options = StreamOptions::new()
+        .buffer_strategy(BufferStrategy::Pooled(pool.clone()));
+
+    let chunks = server::transfer(options, StreamOptions::new(), vec![(&data).to_bytes()]);
+
+    assert_eq!(server::concat(&chunks), data);
+    assert!(chunks.iter().all(|chunk| chunk.len() <= 16 * 1024));
+
+    // A single stream only ever uses one buffer at a time, which keeps being
+    // returned to the pool and taken out again
+    assert_eq!(pool.idle(), 1);
+}
+
+#[test]
+pub fn test_tcp_event_budget() {
+    let data = data();
+    let options = StreamOptions::new()
+        .buffer_strategy(BufferStrategy::Fixed(1024))
+        .event_budget(1);
+
+    let chunks = server::transfer(options, StreamOptions::new(), vec![(&data).to_bytes()]);
+
+    assert_eq!(server::concat(&chunks), data);
+}
+
+#[test]
+pub fn test_adaptive_buffer_min_larger_than_max() {
+    let data = data();
+    let options = StreamOptions::new()
+        .buffer_strategy(BufferStrategy::Adaptive { min: 64 * 1024, max: 1024 });
+
+    let chunks = server::transfer(options, StreamOptions::new(), vec![(&data).to_bytes()]);
+
+    // The minimum is brought down to the maximum
+    assert_eq!(server::concat(&chunks), data);
+    assert!(chunks.iter().all(|chunk| chunk.len() <= 1024));
+}
+
+#[test]
+pub fn test_fixed_buffer_zero_size() {
+    let data = b"hello world";
+    let options = StreamOptions::new()
+        .buffer_strategy(BufferStrategy::Fixed(0));
+
+    let chunks = server::transfer(options, StreamOptions::new(), vec![data.to_bytes()]);
+
+    // Each read uses a buffer of the smallest size
+    assert_eq!(server::concat(&chunks), data);
+    assert!(chunks.iter().all(|chunk| chunk.len() == 1));
+}
+
+#[test]
+pub fn test_buffer_pool_too_large() {
+    let pool = BufferPool::new(usize::MAX, 8);
+
+    // The buffer size is brought down to the largest supported one
+    assert_eq!(format!("{:?}", pool), "BufferPool { buf_size: 2147483648, capacity: 8 }");
+}
@@ -0,0 +1,53 @@
+use {addr, server};
+use bytes::ToBytes;
+use eio::{Error, Reactor};
+use eio::frame::{self, Frame};
+use eventual::{Async, AsyncError};
+use std::io;
+
+#[test]
+pub fn test_tcp_connect() {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let sock = server::listen(&addr);
+
+    // Greet the first client
+    reactor.accept(sock)
+        .take(1)
+        .each(|conn| {
+            let (tx, _) = conn.into_pair();
+            tx.send(b"hello".to_bytes()).fire()
+        })
+        .fire();
+
+    let conn = reactor.connect(&addr).await().unwrap();
+
+    assert_eq!(conn.peer_addr(), Some(addr));
+    assert!(conn.local_addr().is_some());
+
+    let (_, rx) = conn.into_pair();
+
+    let (greeting, _) = rx.frame_one(frame::Len::new(5))
+        .await().unwrap()
+        .expect("unexpected EOF");
+
+    assert_eq!(greeting, b"hello".to_bytes());
+}
+
+#[test]
+pub fn test_tcp_connect_refused() {
+    // Nothing is listening on the port
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    match reactor.connect(&addr).await() {
+        Err(AsyncError::Failed(Error::Io(e))) => {
+            assert_eq!(e.kind(), io::ErrorKind::ConnectionRefused);
+        }
+        Err(e) => panic!("unexpected error; err={:?}", e),
+        Ok(_) => panic!("expected the connect to fail"),
+    }
+}
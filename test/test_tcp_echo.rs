@@ -1,9 +1,9 @@
 use addr;
 use bytes::{Bytes, ByteStr, ToBytes};
 use mio::{tcp, Socket};
-use eio::{Reactor, Pair, Future};
+use eio::{Reactor, Pair};
 use eio::frame::{self, Frame};
-use eventual::{self, Async};
+use eventual::{self, Async, Future};
 use std::sync::mpsc;
 
 #[test]
@@ -26,11 +26,13 @@ pub fn test_tcp_echo() {
      * ===== Server =====
      */
     let server = reactor.accept(sock)
+        .map_err(|_| ())
         .take(1)
-        .process(1, |(tx, rx)| {
+        .process(1, |conn| {
             debug!("GOT A SOCKET");
+            let (tx, rx) = conn.into_pair();
 
-            rx.reduce_async(tx, |tx, chunk| {
+            rx.map_err(|_| ()).reduce_async(tx, |tx, chunk| {
                 debug!("Received a chunk! {:?}", chunk);
                 // Echo it back
                 tx.send(chunk)
@@ -48,7 +50,7 @@ pub fn test_tcp_echo() {
     let (sock, _) = tcp::connect(&addr).unwrap();
 
     // Initiate the connection
-    let pipe = reactor.stream(sock);
+    let pipe = reactor.stream(sock).into_pair();
 
     /*
      *
@@ -56,7 +58,7 @@ pub fn test_tcp_echo() {
      *
      */
     fn echo_message(msg: Bytes, (tx, rx): Pair<Bytes>,
-                    sender: mpsc::Sender<Bytes>) -> Future<(Pair<Bytes>, mpsc::Sender<Bytes>)> {
+                    sender: mpsc::Sender<Bytes>) -> Future<(Pair<Bytes>, mpsc::Sender<Bytes>), ()> {
 
         let len = msg.len();
         let expect = msg.clone();
@@ -86,7 +88,7 @@ pub fn test_tcp_echo() {
         let rest = rest.and_then(|rest| {
             debug!("REST DONE");
             Ok(rest)
-        });
+        }).map_err(|_| ());
 
         debug!("WAITING FOR BUSY / REST JOIN");
         eventual::join((busy, rest)).and_then(|v| {
@@ -0,0 +1,34 @@
+use {addr, server};
+use bytes::{ByteStr, ToBytes};
+use eio::Reactor;
+use eio::frame::{self, Frame};
+use eventual::Async;
+
+#[test]
+pub fn test_tcp_half_close() {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let conns = reactor.accept(server::listen(&addr));
+
+    let (tx, rx) = reactor.connect(&addr).await().unwrap().into_pair();
+
+    let (srv_tx, srv_rx) = server::accept(conns).into_pair();
+
+    // Send the request, then end the write stream
+    drop(tx.send(b"ping".to_bytes()).await().unwrap());
+
+    // The server reads the request until EOF
+    let len = srv_rx.iter().fold(0, |len, chunk| len + chunk.len());
+    assert_eq!(len, 4);
+
+    // The client can still read the response
+    drop(srv_tx.send(b"pong".to_bytes()).await().unwrap());
+
+    let (response, _) = rx.frame_one(frame::Len::new(4))
+        .await().unwrap()
+        .expect("unexpected EOF");
+
+    assert_eq!(response, b"pong".to_bytes());
+}
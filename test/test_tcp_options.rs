@@ -0,0 +1,112 @@
+use {addr, server};
+use bytes::{ByteStr, ToBytes};
+use libc::{self, c_int};
+use mio::tcp;
+use nix::sys::socket::{self, getsockopt, sockopt};
+use eio::{Reactor, StreamOptions};
+use eio::frame::{self, Frame};
+use eventual::Async;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::Duration;
+
+fn options() -> StreamOptions {
+    StreamOptions::new()
+        .nodelay(true)
+        .keepalive(Duration::from_secs(60))
+        .send_buffer_size(256 * 1024)
+        .recv_buffer_size(256 * 1024)
+        .socket_linger(Duration::from_secs(1))
+}
+
+#[test]
+pub fn test_tcp_socket_options() {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    // Listener level options apply to every accepted socket
+    server::echo(&reactor, &addr, options());
+
+    let msg = "hello world".as_bytes().to_bytes();
+
+    // Connected through the reactor
+    let (tx, rx) = reactor.connect_with(&addr, options()).await().unwrap().into_pair();
+    drop(tx.send(msg.clone()).await().unwrap());
+
+    let (echo, _) = rx.frame_one(frame::Len::new(msg.len()))
+        .await().unwrap()
+        .expect("unexpected EOF");
+
+    assert_eq!(echo, msg);
+
+    // Connected by hand, then handed to the reactor
+    let (sock, _) = tcp::connect(&addr).unwrap();
+    let (tx, rx) = reactor.stream_with(sock, options()).into_pair();
+    drop(tx.send(msg.clone()).await().unwrap());
+
+    let (echo, _) = rx.frame_one(frame::Len::new(msg.len()))
+        .await().unwrap()
+        .expect("unexpected EOF");
+
+    assert_eq!(echo, msg);
+
+    reactor.shutdown().await().unwrap();
+}
+
+#[test]
+pub fn test_tcp_socket_options_applied() {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    server::echo(&reactor, &addr, StreamOptions::new());
+
+    let options = StreamOptions::new()
+        .nodelay(true)
+        .keepalive(Duration::from_secs(60))
+        .send_buffer_size(256 * 1024)
+        .recv_buffer_size(256 * 1024)
+        .socket_linger(Duration::from_millis(500));
+
+    let (sock, _) = tcp::connect(&addr).unwrap();
+    let fd = sock.as_raw_fd();
+
+    // The options are applied before the stream is handed to the event loop
+    let conn = reactor.stream_with(sock, options);
+
+    assert!(getsockopt(fd, sockopt::TcpNoDelay).unwrap());
+    assert!(getsockopt(fd, sockopt::KeepAlive).unwrap());
+
+    // The kernel may double the requested buffer sizes
+    assert!(int_option(fd, socket::SO_SNDBUF) >= 256 * 1024);
+    assert!(int_option(fd, socket::SO_RCVBUF) >= 256 * 1024);
+
+    // Partial seconds are rounded up instead of resetting the connection
+    let linger = getsockopt(fd, sockopt::Linger).unwrap();
+    assert!(linger.l_onoff != 0);
+    assert_eq!(linger.l_linger, 1);
+
+    drop(conn);
+
+    reactor.shutdown().await().unwrap();
+}
+
+// Reads an integer SOL_SOCKET option that nix does not provide
+fn int_option(fd: RawFd, name: c_int) -> c_int {
+    extern {
+        fn getsockopt(fd: c_int, level: c_int, name: c_int,
+                      val: *mut libc::c_void, len: *mut libc::socklen_t) -> c_int;
+    }
+
+    let mut val: c_int = 0;
+    let mut len = mem::size_of::<c_int>() as libc::socklen_t;
+
+    let res = unsafe {
+        getsockopt(fd, socket::SOL_SOCKET, name,
+                   &mut val as *mut c_int as *mut libc::c_void, &mut len)
+    };
+
+    assert_eq!(res, 0);
+    val
+}
@@ -0,0 +1,196 @@
+use {addr, server};
+use bytes::{ByteStr, ToBytes};
+use eio::{Error, Reactor, StreamOptions};
+use eventual::{Async, AsyncError};
+use std::io::Read;
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+pub fn test_tcp_read_timeout() {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let sock = server::listen(&addr);
+
+    let options = StreamOptions::new()
+        .read_timeout(Duration::from_millis(50));
+
+    let conns = reactor.accept_with(sock, options);
+
+    // Connect, but never send any data
+    let client = reactor.connect(&addr).await().unwrap();
+
+    let (_, rx) = server::accept(conns).into_pair();
+
+    match rx.await() {
+        Err(AsyncError::Failed(Error::Timeout)) => {}
+        Err(e) => panic!("unexpected error; err={:?}", e),
+        Ok(_) => panic!("expected the read to time out"),
+    }
+
+    drop(client);
+}
+
+#[test]
+pub fn test_tcp_read_timeout_flushes_pending_writes() {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let sock = server::listen(&addr);
+
+    let options = StreamOptions::new()
+        .read_timeout(Duration::from_millis(50));
+
+    let conns = reactor.accept_with(sock, options);
+
+    // The client never sends anything, but keeps its write half open
+    let (_tx, rx) = reactor.connect(&addr).await().unwrap().into_pair();
+
+    let (tx, srv_rx) = server::accept(conns).into_pair();
+
+    // More data than fits in the socket buffers, so it is still pending when
+    // the read times out
+    let data = vec![0u8; 4 * 1024 * 1024];
+    let busy = tx.send((&data).to_bytes());
+
+    // Wait for data that never comes
+    match srv_rx.await() {
+        Err(AsyncError::Failed(Error::Timeout)) => {}
+        Err(e) => panic!("unexpected error; err={:?}", e),
+        Ok(_) => panic!("expected the read to time out"),
+    }
+
+    drop(busy);
+
+    // All of the pending data is written before the socket is closed
+    let len = rx.iter().fold(0, |len, chunk| len + chunk.len());
+    assert_eq!(len, data.len());
+}
+
+#[test]
+pub fn test_tcp_write_timeout_reset_by_progress() {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let options = StreamOptions::new()
+        .send_buffer_size(64 * 1024)
+        .write_timeout(Duration::from_millis(50));
+
+    let conns = reactor.accept_with(server::listen(&addr), options);
+
+    let mut client = TcpStream::connect(&addr).unwrap();
+
+    let (tx, _) = server::accept(conns).into_pair();
+
+    let data = vec![0u8; 8 * 1024 * 1024];
+    drop(tx.send((&data).to_bytes()).await().unwrap());
+
+    // Read slowly, so the write takes several times the timeout but keeps
+    // making progress
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut len = 0;
+
+    loop {
+        match client.read(&mut buf).unwrap() {
+            0 => break,
+            n => len += n,
+        }
+
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    assert_eq!(len, data.len());
+}
+
+#[test]
+pub fn test_tcp_idle_timeout() {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let options = StreamOptions::new()
+        .idle_timeout(Duration::from_millis(50));
+
+    let conns = reactor.accept_with(server::listen(&addr), options);
+
+    let client = reactor.connect(&addr).await().unwrap();
+    let (tx, rx) = server::accept(conns).into_pair();
+
+    // Neither side sends anything
+    match rx.await() {
+        Err(AsyncError::Failed(Error::Timeout)) => {}
+        Err(e) => panic!("unexpected error; err={:?}", e),
+        Ok(_) => panic!("expected the stream to time out"),
+    }
+
+    // The producer sees that the stream is closed
+    assert!(tx.await().is_err());
+
+    drop(client);
+}
+
+#[test]
+pub fn test_tcp_write_timeout() {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let options = StreamOptions::new()
+        .write_timeout(Duration::from_millis(50));
+
+    let conns = reactor.accept_with(server::listen(&addr), options);
+
+    // The client never reads
+    let client = reactor.connect(&addr).await().unwrap();
+    let (tx, rx) = server::accept(conns).into_pair();
+
+    // More data than fits in the socket buffers, so the write stalls
+    let data = vec![0u8; 16 * 1024 * 1024];
+    let tx = tx.send((&data).to_bytes()).await().unwrap();
+
+    match rx.await() {
+        Err(AsyncError::Failed(Error::Timeout)) => {}
+        Err(e) => panic!("unexpected error; err={:?}", e),
+        Ok(_) => panic!("expected the stream to time out"),
+    }
+
+    // The producer is failed as well, without having to send more data first
+    assert!(tx.await().is_err());
+
+    drop(client);
+}
+
+#[test]
+pub fn test_tcp_linger_timeout_discards_pending_writes() {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let options = StreamOptions::new()
+        .send_buffer_size(64 * 1024)
+        .linger_timeout(Duration::from_millis(50));
+
+    let conns = reactor.accept_with(server::listen(&addr), options);
+
+    // Not read from until the linger deadline has passed
+    let mut client = TcpStream::connect(&addr).unwrap();
+
+    let (tx, _) = server::accept(conns).into_pair();
+
+    // Ending the write stream with data still pending starts the deadline
+    let data = vec![0u8; 16 * 1024 * 1024];
+    drop(tx.send((&data).to_bytes()).await().unwrap());
+
+    reactor.timeout(Duration::from_millis(200)).await().unwrap();
+
+    // The data left over at the deadline was discarded and the socket closed
+    let mut received = vec![];
+    let _ = client.read_to_end(&mut received);
+
+    assert!(received.len() < data.len());
+}
@@ -0,0 +1,147 @@
+use {addr, server};
+use bytes::{Bytes, ToBytes};
+use mio;
+use nix::fcntl::{fcntl, O_NONBLOCK};
+use nix::fcntl::FcntlArg::F_SETFL;
+use nix::sys::socket::{self, AddressFamily, SockFlag, SockType};
+use nix::unistd;
+use eio::{BufferStrategy, Reactor, StreamOptions};
+use eventual::Async;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+pub fn test_tcp_write_many_chunks() {
+    let chunks: Vec<Bytes> = (0..500)
+        .map(|i| format!("chunk {};", i).as_bytes().to_bytes())
+        .collect();
+
+    let expect: Vec<u8> = (0..500)
+        .flat_map(|i| format!("chunk {};", i).into_bytes())
+        .collect();
+
+    let res = server::transfer(StreamOptions::new(), StreamOptions::new(), chunks);
+    assert_eq!(server::concat(&res), expect);
+}
+
+#[test]
+pub fn test_tcp_write_budget() {
+    let data: Vec<u8> = (0..4 * 1024 * 1024).map(|i| i as u8).collect();
+    let options = StreamOptions::new().event_budget(1);
+
+    let res = server::transfer(StreamOptions::new(), options, vec![(&data).to_bytes()]);
+    assert_eq!(server::concat(&res), data);
+}
+
+#[test]
+pub fn test_tcp_busy_stream_does_not_starve_others() {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    // Small buffers and a budget of one, so that the busy stream always has
+    // more to read and write when its budget is used up
+    let options = StreamOptions::new()
+        .buffer_strategy(BufferStrategy::Fixed(1024))
+        .event_budget(1);
+
+    server::echo(&reactor, &addr, options);
+
+    // Keep one connection busy, writing as fast as possible and reading the
+    // echo back
+    let busy = TcpStream::connect(&addr).unwrap();
+    let echoed = Arc::new(AtomicUsize::new(0));
+
+    let writer = {
+        let mut sock = busy.try_clone().unwrap();
+
+        thread::spawn(move || {
+            let chunk = [0; 64 * 1024];
+            while sock.write_all(&chunk).is_ok() {}
+        })
+    };
+
+    let reader = {
+        let mut sock = busy.try_clone().unwrap();
+        let echoed = echoed.clone();
+
+        thread::spawn(move || {
+            let mut buf = [0; 64 * 1024];
+
+            while let Ok(n) = sock.read(&mut buf) {
+                if n == 0 { break; }
+                echoed.fetch_add(n, Ordering::SeqCst);
+            }
+        })
+    };
+
+    // Wait for the busy connection to get going
+    while echoed.load(Ordering::SeqCst) == 0 {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    // The other connection keeps getting its turn on the same event loop
+    let mut sock = TcpStream::connect(&addr).unwrap();
+    sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    for _ in 0..10 {
+        let before = echoed.load(Ordering::SeqCst);
+
+        let mut buf = [0; 4];
+        sock.write_all(b"ping").unwrap();
+        sock.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+
+        // The busy connection was not stalled by the other one either
+        while echoed.load(Ordering::SeqCst) == before {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    busy.shutdown(Shutdown::Both).unwrap();
+    writer.join().unwrap();
+    reader.join().unwrap();
+
+    reactor.shutdown().await().unwrap();
+}
+
+#[test]
+pub fn test_queued_chunks_written_together() {
+    let reactor = Reactor::start().unwrap();
+
+    // Datagram sockets keep write boundaries, so every write shows up as a
+    // single datagram on the other end
+    let (wr, rd) = socket::socketpair(AddressFamily::Unix, SockType::Datagram, 0, SockFlag::empty()).unwrap();
+    fcntl(wr, F_SETFL(O_NONBLOCK)).unwrap();
+
+    // Fill the socket, so that the chunks queue up in the stream
+    let mut filled = 0;
+
+    while socket::send(wr, b"x", 0).is_ok() {
+        filled += 1;
+    }
+
+    let (tx, _) = reactor.register(mio::Io::new(wr));
+
+    let tx = tx.send(b"header".to_bytes()).await().unwrap();
+    let tx = tx.send(b"body".to_bytes()).await().unwrap();
+
+    // Make room, then both chunks go out in one write
+    let mut buf = [0; 64];
+
+    for _ in 0..filled {
+        assert_eq!(socket::recv(rd, &mut buf, 0).unwrap(), 1);
+    }
+
+    let n = socket::recv(rd, &mut buf, 0).unwrap();
+    assert_eq!(&buf[..n], b"headerbody");
+
+    drop(tx);
+    unistd::close(rd).unwrap();
+
+    reactor.shutdown().await().unwrap();
+}
@@ -0,0 +1,34 @@
+use eio::{Reactor, ReactorBuilder};
+use eventual::Async;
+use std::time::Duration;
+use time;
+
+#[test]
+pub fn test_timeout() {
+    let tick = Duration::from_millis(10);
+
+    let reactor = ReactorBuilder::new()
+        .timer_tick(tick)
+        .start().unwrap();
+
+    let dur = Duration::from_millis(100);
+    let start = time::precise_time_ns();
+
+    reactor.timeout(dur)
+        .await().unwrap();
+
+    // The timer never fires early
+    assert!(time::precise_time_ns() - start >= 100_000_000);
+}
+
+#[test]
+pub fn test_interval() {
+    let reactor = Reactor::start().unwrap();
+
+    let ticks = reactor.interval(Duration::from_millis(10))
+        .take(3)
+        .iter()
+        .count();
+
+    assert_eq!(ticks, 3);
+}
@@ -0,0 +1,210 @@
+use addr;
+use bytes::{Bytes, ToBytes};
+use mio::udp;
+use nix::sys::socket::{self, MSG_DONTWAIT, MSG_PEEK};
+use eio::{BufferStrategy, Error, Reactor, StreamOptions};
+use eventual::{Async, AsyncError};
+use std::io;
+use std::net::UdpSocket;
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+#[test]
+pub fn test_udp_echo() {
+    let srv_addr = addr::localhost();
+    let client_addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let (srv_tx, srv_rx) = reactor.udp(udp::bind(&srv_addr).unwrap());
+    let (tx, rx) = reactor.udp(udp::bind(&client_addr).unwrap());
+
+    // Bounce every datagram back to where it came from
+    srv_rx.map_err(|_| ()).reduce_async(srv_tx, |tx, datagram| {
+        tx.send(datagram)
+    }).fire();
+
+    let msgs: Vec<Bytes> = vec![
+        b"hello".to_bytes(),
+        b"world".to_bytes(),
+        b"bye".to_bytes(),
+    ];
+
+    let mut tx = tx;
+    let mut rx = rx;
+
+    for msg in msgs {
+        tx = tx.send((msg.clone(), srv_addr)).await().unwrap();
+
+        let ((bytes, from), rest) = match rx.await() {
+            Ok(Some(head)) => head,
+            _ => panic!("expected a datagram"),
+        };
+
+        assert_eq!(bytes, msg);
+        assert_eq!(from, srv_addr);
+
+        rx = rest;
+    }
+
+    drop(tx);
+    drop(rx);
+
+    reactor.shutdown().await().unwrap();
+}
+
+#[test]
+pub fn test_udp_send_without_consumer() {
+    let addr = addr::localhost();
+    let peer_addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let peer = UdpSocket::bind(&peer_addr).unwrap();
+    peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    let (tx, rx) = reactor.udp(udp::bind(&addr).unwrap());
+
+    // Sending still works once the consumer is gone
+    drop(rx);
+
+    let tx = tx.send((b"ping".to_bytes(), peer_addr)).await().unwrap();
+
+    let mut buf = [0; 64];
+    let (n, from) = peer.recv_from(&mut buf).unwrap();
+
+    assert_eq!(&buf[..n], b"ping");
+    assert_eq!(from, addr);
+
+    drop(tx);
+
+    reactor.shutdown().await().unwrap();
+}
+
+#[test]
+pub fn test_udp_truncated_datagram() {
+    let addr = addr::localhost();
+    let peer_addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let options = StreamOptions::new()
+        .buffer_strategy(BufferStrategy::Fixed(4));
+
+    let (_tx, rx) = reactor.udp_with(udp::bind(&addr).unwrap(), options);
+
+    let peer = UdpSocket::bind(&peer_addr).unwrap();
+    peer.send_to(b"hello world", &addr).unwrap();
+
+    // The part of the datagram that does not fit in the buffer is lost
+    let ((bytes, from), _) = match rx.await() {
+        Ok(Some(head)) => head,
+        _ => panic!("expected a datagram"),
+    };
+
+    assert_eq!(bytes, b"hell".to_bytes());
+    assert_eq!(from, peer_addr);
+
+    reactor.shutdown().await().unwrap();
+}
+
+#[test]
+pub fn test_udp_read_backpressure() {
+    let addr = addr::localhost();
+    let peer_addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let sock = udp::bind(&addr).unwrap();
+    let fd = sock.as_raw_fd();
+
+    let (_tx, rx) = reactor.udp(sock);
+
+    let peer = UdpSocket::bind(&peer_addr).unwrap();
+    peer.send_to(b"first", &addr).unwrap();
+    peer.send_to(b"second", &addr).unwrap();
+
+    // Give the event loop a chance to read
+    reactor.timeout(Duration::from_millis(50)).await().unwrap();
+
+    // Returns the datagram at the front of the socket's queue
+    let peek = || {
+        let mut buf = [0; 64];
+        let n = socket::recv(fd, &mut buf, MSG_PEEK | MSG_DONTWAIT).unwrap();
+        buf[..n].to_vec()
+    };
+
+    // The consumer has not asked for a datagram yet, so none was read
+    assert_eq!(peek(), b"first");
+
+    let ((bytes, _), rx) = match rx.await() {
+        Ok(Some(head)) => head,
+        _ => panic!("expected a datagram"),
+    };
+
+    assert_eq!(bytes, b"first".to_bytes());
+
+    // Only the datagram the consumer asked for was read
+    assert_eq!(peek(), b"second");
+
+    drop(rx);
+
+    reactor.shutdown().await().unwrap();
+}
+
+#[test]
+pub fn test_udp_send_error() {
+    let addr = addr::localhost();
+    let peer_addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let peer = UdpSocket::bind(&peer_addr).unwrap();
+    peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    let (tx, rx) = reactor.udp(udp::bind(&addr).unwrap());
+
+    // Larger than any UDP datagram can be
+    let data = vec![0u8; 70_000];
+    let tx = tx.send(((&data).to_bytes(), peer_addr)).await().unwrap();
+
+    // The error is reported to the consumer
+    match rx.await() {
+        Err(AsyncError::Failed(Error::Io(_))) => {}
+        Err(e) => panic!("unexpected error; err={:?}", e),
+        Ok(_) => panic!("expected the send to fail"),
+    }
+
+    // Only the oversized datagram was lost, the socket keeps sending
+    let tx = tx.send((b"ping".to_bytes(), peer_addr)).await().unwrap();
+
+    let mut buf = [0; 64];
+    let (n, _) = peer.recv_from(&mut buf).unwrap();
+
+    assert_eq!(&buf[..n], b"ping");
+
+    drop(tx);
+
+    reactor.shutdown().await().unwrap();
+}
+
+#[test]
+pub fn test_udp_producer_failed() {
+    let addr = addr::localhost();
+
+    let reactor = Reactor::start().unwrap();
+
+    let (tx, rx) = reactor.udp(udp::bind(&addr).unwrap());
+
+    tx.fail(Error::Io(io::Error::new(io::ErrorKind::Other, "producer failed")));
+
+    // The consumer sees the producer's error rather than the end of the
+    // stream
+    match rx.await() {
+        Err(AsyncError::Failed(Error::Io(ref e))) if e.kind() == io::ErrorKind::Other => {}
+        Err(e) => panic!("unexpected error; err={:?}", e),
+        Ok(_) => panic!("expected the producer's error"),
+    }
+
+    reactor.shutdown().await().unwrap();
+}
@@ -0,0 +1,120 @@
+use bytes::ToBytes;
+use mio::unix;
+use eio::Reactor;
+use eio::frame::{self, Frame};
+use eventual::Async;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Duration;
+
+fn sock_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("eventual-io-{}.sock", name));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+pub fn test_unix_echo() {
+    let path = sock_path("echo");
+
+    let reactor = Reactor::start().unwrap();
+
+    let conns = reactor.accept_unix(&path).unwrap();
+
+    let (sock, _) = unix::connect(&path).unwrap();
+    let client = reactor.stream_unix(sock);
+
+    let conn = match conns.await() {
+        Ok(Some((conn, _))) => conn,
+        _ => panic!("expected a connection"),
+    };
+
+    // Both ends belong to this process
+    if cfg!(target_os = "linux") {
+        assert!(conn.peer_cred().is_some());
+        assert_eq!(conn.peer_cred(), client.peer_cred());
+    }
+
+    assert!(conn.peer_addr().is_none());
+
+    let (srv_tx, srv_rx) = conn.into_pair();
+    srv_tx.send_all(srv_rx).fire();
+
+    let (tx, rx) = client.into_pair();
+    let tx = tx.send(b"ping".to_bytes()).await().unwrap();
+
+    let (chunk, _) = rx.frame_one(frame::Len::new(4)).await().unwrap().unwrap();
+    assert_eq!(chunk, b"ping".to_bytes());
+
+    drop(tx);
+
+    reactor.shutdown().await().unwrap();
+}
+
+#[test]
+pub fn test_unix_socket_file() {
+    let path = sock_path("file");
+
+    // Leave a stale socket file behind
+    drop(unix::bind(&path).unwrap());
+    assert!(path.exists());
+
+    let reactor = Reactor::start().unwrap();
+
+    let conns = reactor.accept_unix(&path).unwrap();
+
+    // The path is in use now
+    assert!(reactor.accept_unix(&path).is_err());
+
+    // Checking whether the path is in use connected to the listener, the
+    // connection is closed right away
+    let (conn, conns) = match conns.await() {
+        Ok(Some(head)) => head,
+        _ => panic!("expected a connection"),
+    };
+
+    let (_, rx) = conn.into_pair();
+    assert!(rx.await().unwrap().is_none());
+
+    // No other connection was made
+    let (tx, rx) = mpsc::channel();
+
+    conns.receive(move |res| {
+        let _ = tx.send(res.ok().and_then(|head| head).is_some());
+    });
+
+    reactor.timeout(Duration::from_millis(50)).await().unwrap();
+    assert!(rx.try_recv().is_err());
+
+    reactor.shutdown().await().unwrap();
+
+    // The accept stream ends without a connection
+    assert_eq!(rx.recv().unwrap(), false);
+
+    // Closing the listener removes the socket file
+    assert!(!path.exists());
+}
+
+#[test]
+pub fn test_unix_socket_file_replaced() {
+    let path = sock_path("replaced");
+
+    let reactor = Reactor::start().unwrap();
+
+    let conns = reactor.accept_unix(&path).unwrap();
+
+    // Another socket takes over the path while the listener is open
+    fs::remove_file(&path).unwrap();
+    let other = unix::bind(&path).unwrap();
+
+    drop(conns);
+    reactor.shutdown().await().unwrap();
+
+    // Closing the listener leaves the other socket's file alone
+    assert!(path.exists());
+
+    drop(other);
+    fs::remove_file(&path).unwrap();
+}